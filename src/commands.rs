@@ -1,45 +1,57 @@
 use colored::*;
 use std::env;
 use std::fs;
-use std::io::{self, Write};
-use std::os::unix::fs::symlink;
-use std::path::PathBuf;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::time::SystemTime;
 
-use crate::config::get_files_from_config;
+use serde::Serialize;
+
+use crate::config::{self, Hook, Mode, LEGACY_CONFIG_FILE, TOML_CONFIG_FILE};
 use crate::direnv_utils::allow_direnv;
-use crate::file_ops::{copy_files_from_config, cp_cow, link_files_from_config};
+use crate::error::{Error, ErrorCode};
+use crate::file_ops::{self, apply_files_from_config};
 use crate::git_utils;
 use crate::git_utils::{
-    create_worktree, get_default_branch, get_git_root, get_worktrees, pull_latest,
+    create_worktree, get_default_branch, get_git_root, get_repo_root, get_worktrees, pull_latest,
+    Checkout,
 };
+use crate::hooks::{run_hook, HookContext};
+use crate::layout;
+use crate::output::{self, Action};
+use crate::patterns::{self, ManagedItem};
+use crate::picker::pick_worktree;
+use crate::registry::{self, Record, Registry};
+use crate::safety::{self, Problem};
+use crate::shell::{self, Shell};
+use crate::store::Store;
+use crate::sync::{self, SyncAction, SyncState};
 
-fn dirname(branch_name: &str) -> String {
-    branch_name.replace("/", "_")
+/// Flags of `gwt add`; unset (`None`) flags fall back to the `[defaults]` of the config.
+pub struct AddOptions<'a> {
+    pub copy: Option<bool>,
+    pub verbose: Option<bool>,
+    pub pull: Option<bool>,
+    pub base: Option<&'a str>,
+    pub fetch: Option<bool>,
+    pub remote: Option<&'a str>,
+    /// Check out `branch_name` as a detached commit-ish instead of a branch
+    pub detach: bool,
 }
 
-fn worktree_path(branch_name: &str) -> Result<PathBuf, String> {
-    let dirname = branch_name.replace('/', "_");
-
+pub fn add_worktree(branch_name: &str, options: &AddOptions) -> Result<(), Error> {
     let git_root = get_git_root()?;
+    let config = config::load(&git_root)?;
+    let copy = options.copy.unwrap_or(config.defaults.copy);
+    let verbose = options.verbose.unwrap_or(config.defaults.verbose);
+    let pull = options.pull.unwrap_or(config.defaults.pull);
+    let fetch = options.fetch.unwrap_or(config.defaults.fetch);
 
-    let worktree_path = git_root.join(format!("../{dirname}"));
-
-    Ok(worktree_path)
-}
-
-pub fn add_worktree(
-    branch_name: &str,
-    copy: bool,
-    verbose: bool,
-    pull: bool,
-) -> Result<(), String> {
     if verbose {
-        println!("Verbose mode enabled");
+        output::text("Verbose mode enabled");
     }
 
-    let git_root = get_git_root()?;
     env::set_current_dir(&git_root)
         .map_err(|e| format!("Failed to change to git root directory: {e}"))?;
 
@@ -47,123 +59,837 @@ pub fn add_worktree(
         pull_latest()?;
     }
 
-    create_worktree(branch_name, &dirname(branch_name))?;
+    let worktree_path = layout::worktree_path(&config, branch_name)?;
+    registry::check_collision(branch_name, &worktree_path)?;
 
-    let worktree_path = worktree_path(branch_name)?;
-    let worktree_path = fs::canonicalize(&worktree_path).map_err(|e| {
+    let base = options
+        .base
+        .map(str::to_string)
+        .or_else(|| config.defaults.base.clone());
+    if fetch {
+        let fetched_ref = if options.detach {
+            Some(branch_name)
+        } else {
+            base.as_deref()
+        };
+        let remote = match (options.remote, fetched_ref) {
+            (Some(remote), _) => Some(remote.to_string()),
+            (None, Some(fetched_ref)) => remote_of(fetched_ref)?,
+            (None, None) => None,
+        };
+        git_utils::fetch(remote.as_deref().unwrap_or(&config.remote.name))?;
+    }
+
+    let remote_branch: String;
+    let start_commit: String;
+    let mut new_branch_start = None;
+    let checkout = if options.detach {
+        let Some(commit) = git_utils::resolve_commit(branch_name)? else {
+            return Err(Error::new(
+                ErrorCode::NotFound,
+                format!("'{branch_name}' does not point to a commit"),
+            ));
+        };
+        output::info(format!(
+            "Creating detached worktree at {branch_name} ({})",
+            git_utils::commit_summary(&commit)?
+        ));
+        output::field("ref", branch_name);
+        output::field("start_commit", &commit);
+        start_commit = commit;
+        Checkout::Detach(&start_commit)
+    } else if git_utils::local_branch_exists(branch_name)? {
+        if options.base.is_some() || options.remote.is_some() {
+            output::warn(format!(
+                "Branch '{branch_name}' already exists, ignoring --base and --remote"
+            ));
+        }
+        Checkout::Existing
+    } else if let Some(remote) = tracked_remote(branch_name, options.remote)? {
+        if options.base.is_some() {
+            output::warn(format!(
+                "Branch '{branch_name}' exists on {remote}, ignoring --base"
+            ));
+        }
+        remote_branch = format!("{remote}/{branch_name}");
+        output::info(format!(
+            "Creating branch '{branch_name}' tracking {remote_branch}"
+        ));
+        output::field("upstream", &remote_branch);
+        Checkout::Track(&remote_branch)
+    } else {
+        let base = match base {
+            Some(base) => base,
+            None => default_base(&config.remote.name)?,
+        };
+        let Some(commit) = git_utils::resolve_commit(&base)? else {
+            return Err(Error::new(
+                ErrorCode::NotFound,
+                format!("Base '{base}' does not point to a commit"),
+            ));
+        };
+        output::info(format!(
+            "Creating branch '{branch_name}' from {base} at {}",
+            git_utils::commit_summary(&commit)?
+        ));
+        output::field("base", &base);
+        output::field("start_commit", &commit);
+        new_branch_start = Some(commit.clone());
+        start_commit = commit;
+        Checkout::New(&start_commit)
+    };
+
+    let record = Record {
+        detached: options.detach,
+        start_commit: new_branch_start,
+        ..Record::default()
+    };
+    set_up_worktree(
+        &config,
+        &git_root,
+        branch_name,
+        &worktree_path,
+        checkout,
+        record,
+        copy,
+    )
+}
+
+/// Creates the worktree `name` at `worktree_path`, registers it and puts the managed files, direnv
+/// and the post-add hook in place.
+fn set_up_worktree(
+    config: &config::Config,
+    git_root: &Path,
+    name: &str,
+    worktree_path: &Path,
+    checkout: Checkout,
+    mut record: Record,
+    copy: bool,
+) -> Result<(), Error> {
+    layout::exclude_if_nested(worktree_path)?;
+    create_worktree(name, worktree_path, checkout)?;
+
+    let worktree_path = fs::canonicalize(worktree_path).map_err(|e| {
         format!(
             "Failed to canonicalize worktree path '{}': {e}",
             worktree_path.display()
         )
     })?;
+    record.path = worktree_path.clone();
+    let detached = record.detached;
+    let mut registry = Registry::load()?;
+    registry.insert(name, record);
+    registry.save()?;
 
-    if copy {
-        copy_files_from_config(&worktree_path)?;
+    // Managed files come from the primary worktree wherever gwt runs, so links don't point
+    // into a worktree that is removed later. A new primary worktree can't link to itself and
+    // gets copies from the current worktree instead.
+    let primary = registry::primary_worktree(config)?;
+    let store = Store::load()?;
+    let default_mode = if copy { Mode::Copy } else { Mode::Link };
+    let source_worktree = if primary == worktree_path {
+        output::info("The new worktree is the primary worktree, copying managed files into it.");
+        let entries = config
+            .files
+            .iter()
+            .cloned()
+            .map(|mut entry| {
+                if entry.mode == Some(Mode::Link) {
+                    entry.mode = Some(Mode::Copy);
+                }
+                entry
+            })
+            .collect::<Vec<_>>();
+        apply_files_from_config(&worktree_path, git_root, &entries, Mode::Copy, &store)?;
+        git_root
     } else {
-        link_files_from_config(&worktree_path, &git_root)?;
-    }
+        if primary != git_root {
+            output::info(format!(
+                "Using managed files from the primary worktree {}",
+                primary.display()
+            ));
+        }
+        apply_files_from_config(
+            &worktree_path,
+            &primary,
+            &config.files,
+            default_mode,
+            &store,
+        )?;
+        primary.as_path()
+    };
 
     if worktree_path.join(".envrc").exists() {
         allow_direnv(&worktree_path)?;
     }
 
-    println!("{}", worktree_path.display());
+    let repo_root = get_repo_root()?;
+    let branch = (!detached).then_some(name);
+    let context = HookContext {
+        branch,
+        worktree: &worktree_path,
+        source_worktree: Some(source_worktree),
+        repo_root: &repo_root,
+    };
+    run_hook(config, Hook::PostAdd, &worktree_path, &context)?;
+
+    shell::change_directory(&worktree_path)?;
+
+    output::created(&worktree_path);
+    if let Some(branch) = branch {
+        output::field("branch", branch);
+    }
+    output::field("worktree", &worktree_path);
+    output::text(worktree_path.display());
     Ok(())
 }
 
-pub fn sync_worktrees(copy_flag: bool) -> Result<(), String> {
+/// `<remote>/<default branch>` if the remote has one, otherwise the current HEAD.
+fn default_base(remote: &str) -> Result<String, Error> {
+    if let Ok(default_branch) = get_default_branch(remote) {
+        let base = format!("{remote}/{default_branch}");
+        if git_utils::resolve_commit(&base)?.is_some() {
+            return Ok(base);
+        }
+    }
+    Ok("HEAD".to_string())
+}
+
+/// The remote whose branch `branch_name` should track, `None` if no remote has the branch.
+/// When several remotes have it, `requested` (`--remote`) or the user decides.
+fn tracked_remote(branch_name: &str, requested: Option<&str>) -> Result<Option<String>, Error> {
+    let mut remotes = git_utils::remotes_with_branch(branch_name)?;
+
+    if let Some(requested) = requested {
+        if remotes.iter().any(|r| r == requested) {
+            return Ok(Some(requested.to_string()));
+        }
+        return Err(Error::new(
+            ErrorCode::NotFound,
+            format!("Branch '{branch_name}' does not exist on remote '{requested}'"),
+        ));
+    }
+
+    if remotes.len() <= 1 {
+        return Ok(remotes.pop());
+    }
+
+    let candidates = remotes
+        .iter()
+        .map(|remote| format!("{remote}/{branch_name}"))
+        .collect::<Vec<_>>();
+    if !io::stdin().is_terminal() {
+        return Err(Error::new(
+            ErrorCode::Ambiguous,
+            format!(
+                "Branch '{branch_name}' exists on several remotes: {}. Choose one with --remote <name>",
+                candidates.join(", ")
+            ),
+        ));
+    }
+
+    let mut question = format!("Branch '{branch_name}' exists on several remotes:\n");
+    for (i, candidate) in candidates.iter().enumerate() {
+        question.push_str(&format!("  {}) {candidate}\n", i + 1));
+    }
+    question.push_str(&format!(
+        "Which one should it track? (1-{}): ",
+        remotes.len()
+    ));
+    output::prompt(question)?;
+
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .map_err(|e| format!("Failed to read line: {e}"))?;
+    match answer.trim().parse::<usize>() {
+        Ok(choice) if (1..=remotes.len()).contains(&choice) => {
+            Ok(Some(remotes.swap_remove(choice - 1)))
+        }
+        _ => Err(Error::new(ErrorCode::Cancelled, "No remote selected")),
+    }
+}
+
+/// The remote a ref like `upstream/main` belongs to.
+fn remote_of(base: &str) -> Result<Option<String>, Error> {
+    Ok(git_utils::get_remotes()?
+        .into_iter()
+        .find(|remote| base.starts_with(&format!("{remote}/"))))
+}
+
+/// Local ref the head of pull request `number` is fetched into.
+fn pull_request_ref(number: u64) -> String {
+    format!("refs/gwt/pr/{number}")
+}
+
+/// Fetches pull request `number` from the remote and checks it out into a new worktree `pr-<N>`.
+pub fn review(number: u64, remote: Option<&str>) -> Result<(), Error> {
     let git_root = get_git_root()?;
+    let config = config::load(&git_root)?;
     env::set_current_dir(&git_root)
         .map_err(|e| format!("Failed to change to git root directory: {e}"))?;
-    let worktrees = get_worktrees()?;
-    let config_path = git_root.join(".gwtconfig");
-    let files_to_sync = get_files_from_config(&config_path)?;
 
-    if files_to_sync.is_empty() {
-        println!(
-            "{} No .gwtconfig file found or it is empty. No files to sync.",
-            "Info:".green()
-        );
+    let remote = remote.unwrap_or(&config.remote.name);
+    let branch_name = format!("pr-{number}");
+    let worktree_path = layout::worktree_path(&config, &branch_name)?;
+    registry::check_collision(&branch_name, &worktree_path)?;
+
+    let patterns = match &config.review.ref_pattern {
+        Some(pattern) => vec![pattern.as_str()],
+        None => vec![
+            "refs/pull/{number}/head",
+            "refs/merge-requests/{number}/head",
+        ],
+    };
+    let local_ref = pull_request_ref(number);
+    let mut fetched = None;
+    for pattern in &patterns {
+        let remote_ref = pattern.replace("{number}", &number.to_string());
+        if git_utils::fetch_ref(remote, &remote_ref, &local_ref)? {
+            fetched = Some(remote_ref);
+            break;
+        }
+    }
+    let Some(remote_ref) = fetched else {
+        return Err(Error::new(
+            ErrorCode::NotFound,
+            format!(
+                "Pull request {number} not found on {remote} (tried {})",
+                patterns
+                    .iter()
+                    .map(|p| p.replace("{number}", &number.to_string()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        ));
+    };
+
+    let Some(commit) = git_utils::resolve_commit(&local_ref)? else {
+        return Err(Error::new(
+            ErrorCode::GitFailed,
+            format!("Fetched {remote_ref} but {local_ref} does not point to a commit"),
+        ));
+    };
+    output::info(format!(
+        "Fetched {remote_ref} from {remote} at {}",
+        git_utils::commit_summary(&commit)?
+    ));
+    output::field("pull_request", number);
+    output::field("fetched_ref", &local_ref);
+
+    let record = Record {
+        pull_request: Some(number),
+        ..Record::default()
+    };
+    set_up_worktree(
+        &config,
+        &git_root,
+        &branch_name,
+        &worktree_path,
+        Checkout::New(&commit),
+        record,
+        config.defaults.copy,
+    )
+}
+
+/// A managed item that was changed differently in several worktrees and was not synced.
+#[derive(Serialize)]
+struct SyncConflict<'a> {
+    path: &'a str,
+    versions: &'a [sync::Version],
+}
+
+/// Syncs the managed files between all worktrees. Items changed in one worktree since the last
+/// sync are propagated to the others; items changed in several are conflicts, resolved with
+/// `prefer` (a worktree name or path) or interactively. With `dry_run`, only the plan is shown.
+pub fn sync_worktrees(
+    copy_flag: Option<bool>,
+    prefer: Option<&str>,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let current_dir =
+        env::current_dir().map_err(|e| format!("couldn't get current directory: {e}"))?;
+    let git_root = get_git_root()?;
+    env::set_current_dir(&git_root)
+        .map_err(|e| format!("Failed to change to git root directory: {e}"))?;
+    let synced_worktrees: Vec<git_utils::Worktree> = get_worktrees()?
+        .into_iter()
+        .filter(|w| !w.bare && w.prunable.is_none())
+        .collect();
+    let worktrees: Vec<PathBuf> = synced_worktrees.iter().map(|w| w.path.clone()).collect();
+    let config = config::load(&git_root)?;
+    let files_to_sync = &config.files;
+    let copy_flag = copy_flag.unwrap_or(config.defaults.copy);
+    let preferred = match prefer {
+        Some(target) => Some(resolve_worktree(target, &current_dir)?.path),
+        None => None,
+    };
+
+    let store = Store::load()?;
+    if files_to_sync.is_empty() && store.items.is_empty() {
+        output::info("No managed files configured. No files to sync.");
         return Ok(());
     }
 
-    for item in files_to_sync {
-        let mut most_recent_path: Option<PathBuf> = None;
-        let mut most_recent_time: Option<SystemTime> = None;
+    // Globs are expanded in every worktree, so items that only exist in one of them are found.
+    // Shared items (and what's inside shared directories) are linked to the store instead.
+    let mut items: Vec<ManagedItem> = Vec::new();
+    for worktree in &worktrees {
+        for item in patterns::expand(worktree, files_to_sync)? {
+            if store.covering(&item.path).is_none() && !items.iter().any(|i| i.path == item.path) {
+                items.push(item);
+            }
+        }
+    }
 
-        for worktree in &worktrees {
-            let path = worktree.join(&item);
-            if path.exists() {
-                let metadata = fs::metadata(&path)
-                    .map_err(|e| format!("Failed to get metadata for {}: {e}", path.display()))?;
-                let modified_time = metadata.modified().map_err(|e| {
-                    format!("Failed to get modified time for {}: {e}", path.display())
-                })?;
+    let mut state = SyncState::load()?;
+    let default_mode = if copy_flag { Mode::Copy } else { Mode::Link };
+    let primary = registry::primary_worktree(&config)?;
+    let mut plans = sync::plan_shared(&worktrees, &store)?;
+    plans.extend(sync::plan(
+        &worktrees,
+        &primary,
+        &items,
+        default_mode,
+        &state,
+        |item, versions| resolve_conflict(item, versions, preferred.as_deref(), !dry_run),
+    )?);
 
-                if most_recent_time.is_none() || modified_time > most_recent_time.unwrap() {
-                    most_recent_time = Some(modified_time);
-                    most_recent_path = Some(path);
-                }
+    output::field("primary", &primary);
+    if !store.items.is_empty() {
+        output::field("store", store.root());
+    }
+    if dry_run {
+        output::info(format!(
+            "Linked items point into the primary worktree {}",
+            primary.display()
+        ));
+        if !store.items.is_empty() {
+            output::info(format!(
+                "Shared items point into the store {}",
+                store.root().display()
+            ));
+        }
+        print_sync_plan(&plans, &synced_worktrees);
+        output::field("plan", &plans);
+        output::field("dry_run", true);
+        output::info("Dry run, nothing was changed.");
+        return Ok(());
+    }
+
+    let mut backup_dir: Option<PathBuf> = None;
+    let synced = (|| -> Result<(), Error> {
+        for plan in &plans {
+            if plan.mode == Mode::Template {
+                output::info(format!("'{}' is a template and is not synced.", plan.path));
+            }
+            let Some(hash) = &plan.hash else {
+                continue;
+            };
+            for step in &plan.steps {
+                let Some(from) = &step.from else {
+                    continue;
+                };
+                let backup = match (step.backup, &backup_dir) {
+                    (false, _) => None,
+                    (true, Some(dir)) => Some(dir.clone()),
+                    (true, None) => Some(backup_dir.insert(create_backup_dir()?).clone()),
+                };
+                // Keyed by the escaped full path, since worktree directory names needn't be
+                // unique.
+                let backup = backup.map(|dir| {
+                    let worktree_key = layout::branch_slug(
+                        step.worktree.to_string_lossy().trim_start_matches('/'),
+                    );
+                    dir.join(worktree_key).join(&plan.path)
+                });
+                sync_item(&plan.path, from, step, backup.as_deref())?;
             }
+            state.set(&plan.path, hash);
         }
+        Ok(())
+    })();
+    // Items synced before a failure are remembered, so the next sync doesn't see conflicts.
+    state.save()?;
+    synced?;
+    if let Some(backup_dir) = &backup_dir {
+        output::info(format!(
+            "Replaced files and directories were backed up to {}",
+            backup_dir.display()
+        ));
+        output::field("backup_dir", backup_dir);
+    }
 
-        if let Some(src_path) = most_recent_path {
-            for worktree in &worktrees {
-                let dest_path = worktree.join(&item);
-                if src_path.as_path() != dest_path.as_path() {
-                    if let Some(parent) = dest_path.parent() {
-                        fs::create_dir_all(parent).map_err(|e| {
-                            format!("Failed to create directory {}: {e}", parent.display())
-                        })?;
-                    }
-
-                    if copy_flag {
-                        cp_cow(&src_path, &dest_path)?;
-                        println!(
-                            "{} Synced '{}' to {} (copied)",
-                            "Info:".green(),
-                            item,
-                            worktree.display()
-                        );
-                    } else {
-                        // Attempt to symlink first
-                        let symlink_result = symlink(&src_path, &dest_path);
-                        if symlink_result.is_ok() {
-                            println!(
-                                "{} Synced '{}' to {} (linked)",
-                                "Info:".green(),
-                                item,
-                                worktree.display()
-                            );
-                        } else {
-                            // Fallback to copy if symlink fails
-                            eprintln!(
-                                "{} Failed to symlink '{}' to {} ({:?}). Falling back to copy.",
-                                "Warning:".yellow(),
-                                item,
-                                worktree.display(),
-                                symlink_result.unwrap_err()
-                            );
-                            cp_cow(&src_path, &dest_path)?;
-                            println!(
-                                "{} Synced '{}' to {} (copied)",
-                                "Info:".green(),
-                                item,
-                                worktree.display()
-                            );
-                        }
-                    }
-                }
+    let repo_root = get_repo_root()?;
+    for worktree in &synced_worktrees {
+        let context = HookContext {
+            branch: worktree.branch.as_deref(),
+            worktree: &worktree.path,
+            source_worktree: Some(&primary),
+            repo_root: &repo_root,
+        };
+        run_hook(&config, Hook::PostSync, &worktree.path, &context)?;
+    }
+
+    let conflicts = plans
+        .iter()
+        .filter(|plan| !plan.versions.is_empty())
+        .map(|plan| SyncConflict {
+            path: &plan.path,
+            versions: &plan.versions,
+        })
+        .collect::<Vec<_>>();
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+    output::field("conflicts", &conflicts);
+    let mut message = format!(
+        "{} item(s) were changed differently in several worktrees and were not synced:",
+        conflicts.len()
+    );
+    for conflict in &conflicts {
+        let worktrees = conflict
+            .versions
+            .iter()
+            .filter(|v| v.changed)
+            .flat_map(|v| &v.worktrees)
+            .map(|w| w.display().to_string())
+            .collect::<Vec<_>>();
+        message.push_str(&format!(
+            "\n  - {} (changed in {})",
+            conflict.path,
+            worktrees.join(", ")
+        ));
+    }
+    message.push_str("\nRun 'gwt sync --prefer <worktree>' to keep the version of one worktree.");
+    Err(Error::new(ErrorCode::Conflict, message))
+}
+
+/// Creates a new directory for the backups of one sync, named after the current time, with a
+/// counter appended if a sync in the same second already used that name.
+fn create_backup_dir() -> Result<PathBuf, Error> {
+    let backups = git_utils::get_gwt_dir()?.join("backups");
+    fs::create_dir_all(&backups)
+        .map_err(|e| format!("Failed to create directory {}: {e}", backups.display()))?;
+    let timestamp = SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut attempt = 0;
+    loop {
+        let dir = match attempt {
+            0 => backups.join(timestamp.to_string()),
+            n => backups.join(format!("{timestamp}-{n}")),
+        };
+        match fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(e) => {
+                return Err(format!("Failed to create directory {}: {e}", dir.display()).into())
+            }
+        }
+    }
+}
+
+/// Links or copies `src_path` to `item` in the worktree of `step`, replacing whatever is there.
+fn sync_item(
+    item: &str,
+    src_path: &Path,
+    step: &sync::Step,
+    backup: Option<&Path>,
+) -> Result<(), Error> {
+    let worktree = &step.worktree;
+    let linked = file_ops::install(src_path, &worktree.join(item), step.link, backup)?;
+    let (action, how) = if linked {
+        (Action::Linked, "linked")
+    } else {
+        (Action::Copied, "copied")
+    };
+    let replaced = match step.action {
+        SyncAction::Replace => format!(", replaced because it {}", step.reason),
+        _ => String::new(),
+    };
+    output::item(
+        action,
+        item,
+        worktree,
+        format!(
+            "Synced '{}' to {} ({how}{replaced})",
+            item,
+            worktree.display()
+        ),
+    );
+    Ok(())
+}
+
+/// Prints the plan of `gwt sync --dry-run` as one table per item.
+fn print_sync_plan(plans: &[sync::ItemPlan], worktrees: &[git_utils::Worktree]) {
+    if output::is_json() {
+        return;
+    }
+    let name = |path: &Path| {
+        worktrees
+            .iter()
+            .find(|w| w.path == path)
+            .and_then(|w| w.branch.clone())
+            .unwrap_or_else(|| path.display().to_string())
+    };
+    let name_w = worktrees
+        .iter()
+        .map(|w| name(&w.path).chars().count())
+        .max()
+        .unwrap_or(0);
+
+    for plan in plans {
+        let mode = match plan.mode {
+            _ if plan.shared => "shared",
+            Mode::Link => "link",
+            Mode::Copy => "copy",
+            Mode::Template => "template",
+        };
+        println!("{} ({mode}): {}", plan.path.bold(), plan.reason);
+        for step in &plan.steps {
+            let action = match step.action {
+                SyncAction::Link => "link".green(),
+                SyncAction::Copy => "copy".green(),
+                SyncAction::Replace => "replace".yellow(),
+                SyncAction::Skip => "skip".normal(),
+                SyncAction::Conflict => "conflict".red(),
+            };
+            println!(
+                "  {:<name_w$} {:<8} {}",
+                name(&step.worktree),
+                action,
+                step.reason
+            );
+        }
+    }
+}
+
+/// Picks the version of an item that was changed in several worktrees: the one in `preferred`,
+/// or else, if `interactive`, the one the user chooses after seeing the differences. `None`
+/// leaves the item alone.
+fn resolve_conflict(
+    item: &str,
+    versions: &[sync::Version],
+    preferred: Option<&Path>,
+    interactive: bool,
+) -> Result<Option<String>, Error> {
+    if let Some(preferred) = preferred {
+        if let Some(version) = versions
+            .iter()
+            .find(|v| v.worktrees.iter().any(|w| w == preferred))
+        {
+            output::info(format!(
+                "'{item}' was changed in several worktrees, keeping the version from {}",
+                preferred.display()
+            ));
+            return Ok(Some(version.hash.clone()));
+        }
+    }
+    if !interactive || !io::stdin().is_terminal() {
+        return Ok(None);
+    }
+
+    output::text(format!(
+        "'{item}' was changed differently in several worktrees:"
+    ));
+    for (i, version) in versions.iter().enumerate() {
+        let worktrees = version
+            .worktrees
+            .iter()
+            .map(|w| w.display().to_string())
+            .collect::<Vec<_>>();
+        let state = if version.changed {
+            "changed"
+        } else {
+            "last synced"
+        };
+        output::text(format!("  {}) {} ({state})", i + 1, worktrees.join(", ")));
+    }
+    let first = versions[0].worktrees[0].join(item);
+    for version in &versions[1..] {
+        // git diff exits with 1 when there are differences.
+        let _ = Command::new("git")
+            .arg("--no-pager")
+            .arg("diff")
+            .arg("--no-index")
+            .arg("--")
+            .arg(&first)
+            .arg(version.worktrees[0].join(item))
+            .stdout(output::child_stdout())
+            .status();
+    }
+
+    loop {
+        output::prompt(format!(
+            "Keep which version of '{item}'? [1-{}, s to skip]: ",
+            versions.len()
+        ))?;
+        let mut answer = String::new();
+        io::stdin()
+            .read_line(&mut answer)
+            .map_err(|e| format!("Failed to read line: {e}"))?;
+        let answer = answer.trim();
+        if answer.is_empty() || answer.eq_ignore_ascii_case("s") {
+            return Ok(None);
+        }
+        match answer.parse::<usize>() {
+            Ok(n) if (1..=versions.len()).contains(&n) => {
+                return Ok(Some(versions[n - 1].hash.clone()))
             }
+            _ => output::text("Please enter one of the numbers."),
+        }
+    }
+}
+
+/// Moves `path` of the current worktree into the shared store and replaces it with a symlink to
+/// the store. `gwt add` and `gwt sync` link the other worktrees to it.
+pub fn share(path: &Path) -> Result<(), Error> {
+    let current_dir =
+        env::current_dir().map_err(|e| format!("couldn't get current directory: {e}"))?;
+    let git_root = get_git_root()?;
+    let path = current_dir.join(path);
+    // Only the parent is resolved, a symlink at `path` itself is what gets shared.
+    let parent = path.parent().and_then(|p| fs::canonicalize(p).ok());
+    let relative = match (parent, path.file_name()) {
+        (Some(parent), Some(name)) => parent
+            .join(name)
+            .strip_prefix(&git_root)
+            .ok()
+            .map(|r| r.to_string_lossy().into_owned()),
+        _ => None,
+    };
+    let Some(item) = relative.filter(|r| !r.is_empty() && !Path::new(r).starts_with(".git")) else {
+        return Err(Error::new(
+            ErrorCode::Other,
+            format!(
+                "{} is not a file or directory of the worktree {}",
+                path.display(),
+                git_root.display()
+            ),
+        ));
+    };
+    let dest = git_root.join(&item);
+
+    let mut store = Store::load()?;
+    if let Some(shared) = store.covering(&item) {
+        let shared_path = store.path(shared);
+        if shared == item && fs::canonicalize(&dest).ok() == fs::canonicalize(&shared_path).ok() {
+            output::info(format!("'{item}' is already shared."));
+            output::field("shared", &item);
+            output::field("store", &shared_path);
+            return Ok(());
         }
+        return Err(Error::new(
+            ErrorCode::AlreadyExists,
+            format!(
+                "'{shared}' is already in the shared store. Run 'gwt sync' to link it into every worktree."
+            ),
+        ));
+    }
+    if let Some(shared) = store.within(&item) {
+        return Err(Error::new(
+            ErrorCode::AlreadyExists,
+            format!("'{item}' contains '{shared}', which is already in the shared store"),
+        ));
     }
+    if fs::metadata(&dest).is_err() {
+        return Err(Error::new(
+            ErrorCode::NotFound,
+            format!("'{item}' doesn't exist in {}", git_root.display()),
+        ));
+    }
+    if git_utils::is_tracked(&git_root, &item)? {
+        return Err(Error::new(
+            ErrorCode::Other,
+            format!("'{item}' is tracked by git, only untracked files can be shared"),
+        ));
+    }
+    let shared_path = store.path(&item);
+    if shared_path.symlink_metadata().is_ok() {
+        return Err(Error::new(
+            ErrorCode::AlreadyExists,
+            format!("{} already exists", shared_path.display()),
+        ));
+    }
+
+    // Links in other worktrees that lead to the item would only reach the store through this
+    // worktree, and dangle once it is removed, so they are pointed at the store directly.
+    let original = fs::canonicalize(&dest)
+        .map_err(|e| format!("Failed to resolve {}: {e}", dest.display()))?;
+    let linked_worktrees = get_worktrees()?
+        .into_iter()
+        .filter(|w| !w.bare && w.prunable.is_none() && w.path != git_root)
+        .map(|w| w.path)
+        .filter(|worktree| {
+            let link = worktree.join(&item);
+            link.is_symlink() && fs::canonicalize(link).is_ok_and(|target| target == original)
+        })
+        .collect::<Vec<_>>();
+
+    // A symlinked item (e.g. a link into the primary worktree) is shared by copying what it
+    // points to; the original stays where it is until 'gwt sync' replaces it with a link.
+    if dest.is_symlink() {
+        if let Some(parent) = shared_path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create directory {}: {e}", parent.display()))?;
+        }
+        file_ops::cp_cow(&original, &shared_path)?;
+    } else {
+        file_ops::move_path(&dest, &shared_path)?;
+    }
+    if let Err(e) = file_ops::install(&shared_path, &dest, true, None) {
+        if dest.symlink_metadata().is_err() {
+            file_ops::move_path(&shared_path, &dest)?;
+        } else {
+            file_ops::remove_path(&shared_path)?;
+        }
+        return Err(e.into());
+    }
+    store.insert(&item);
+    store.save()?;
 
+    // The symlink is an untracked file to git, which would keep 'gwt remove' from removing
+    // the worktree.
+    if !git_utils::is_ignored(&git_root, &item)? {
+        let pattern = format!("/{item}");
+        git_utils::exclude(&pattern)?;
+        output::info(format!(
+            "Added '{pattern}' to info/exclude, so git ignores it in every worktree."
+        ));
+    }
+
+    output::item(
+        Action::Linked,
+        &item,
+        &git_root,
+        format!(
+            "Moved '{item}' to the shared store {} and linked it",
+            store.root().display()
+        ),
+    );
+    for worktree in &linked_worktrees {
+        file_ops::install(&shared_path, &worktree.join(&item), true, None)?;
+        output::item(
+            Action::Linked,
+            &item,
+            worktree,
+            format!(
+                "Re-linked '{item}' in {} to the shared store",
+                worktree.display()
+            ),
+        );
+    }
+    output::field("shared", &item);
+    output::field("store", &shared_path);
+    output::field("relinked", &linked_worktrees);
+    output::info("Run 'gwt sync' to link it into the other worktrees.");
     Ok(())
 }
 
-pub fn clone_repo(repo: &str) -> Result<(), String> {
+pub fn clone_repo(repo: &str) -> Result<(), Error> {
     let repo_name = repo.split('/').next_back().unwrap_or(repo);
-    println!("Cloning into '{repo_name}'...");
+    output::text(format!("Cloning into '{repo_name}'..."));
 
     fs::create_dir(repo_name)
         .map_err(|e| format!("Failed to create directory {repo_name}: {e}"))?;
@@ -176,50 +902,88 @@ pub fn clone_repo(repo: &str) -> Result<(), String> {
         .arg(".bare")
         .arg("--")
         .arg("--bare")
+        .stdout(output::child_stdout())
         .status()
         .map_err(|e| format!("Failed to execute gh repo clone: {e}"))?;
 
     if !clone_status.success() {
-        return Err("Failed to clone repository".to_string());
+        return Err(Error::new(
+            ErrorCode::GitFailed,
+            "Failed to clone repository",
+        ));
     }
 
     fs::write(".git", "gitdir: ./.bare").map_err(|e| format!("Failed to write .git file: {e}"))?;
 
-    let default_branch = get_default_branch()?;
+    let default_branch = get_default_branch(&config::Remote::default().name)?;
 
-    println!("Adding worktree '{default_branch}' for branch '{default_branch}'");
+    output::text(format!(
+        "Adding worktree '{default_branch}' for branch '{default_branch}'"
+    ));
     let worktree_status = Command::new("git")
         .arg("worktree")
         .arg("add")
         .arg(&default_branch)
         .arg(&default_branch)
+        .stdout(output::child_stdout())
         .status()
         .map_err(|e| format!("Failed to create worktree: {e}"))?;
 
     if !worktree_status.success() {
-        return Err(format!("Failed to create '{default_branch}' worktree"));
+        return Err(Error::new(
+            ErrorCode::GitFailed,
+            format!("Failed to create '{default_branch}' worktree"),
+        ));
     }
 
-    println!(
+    let repo_path =
+        env::current_dir().map_err(|e| format!("couldn't get current directory: {e}"))?;
+    let worktree_path = repo_path.join(&default_branch);
+    let config = config::load(&worktree_path)?;
+    let repo_root = get_repo_root()?;
+    let context = HookContext {
+        branch: Some(&default_branch),
+        worktree: &worktree_path,
+        source_worktree: None,
+        repo_root: &repo_root,
+    };
+    run_hook(&config, Hook::PostClone, &worktree_path, &context)?;
+
+    output::created(&repo_path);
+    output::created(&repo_path.join(&default_branch));
+    output::field("repository", repo);
+    output::field("default_branch", &default_branch);
+    output::field("worktree", repo_path.join(&default_branch));
+    output::text(format!(
         "{} Successfully cloned {} and set up worktree in '{}/{}'",
         "Success:".green(),
         repo,
         repo_name,
         default_branch
-    );
+    ));
     Ok(())
 }
 
-pub fn init_gwtconfig() -> Result<(), String> {
+pub fn init_gwtconfig() -> Result<(), Error> {
     let git_root = get_git_root()?;
     let gitignore_path = git_root.join(".gitignore");
-    let gwtconfig_path = git_root.join(".gwtconfig");
+    let gwtconfig_path = git_root.join(LEGACY_CONFIG_FILE);
+
+    if git_root.join(TOML_CONFIG_FILE).exists() {
+        return Err(Error::new(
+            ErrorCode::Config,
+            format!("This repository uses {TOML_CONFIG_FILE}; add patterns to its [files] section instead."),
+        ));
+    }
 
     if !gitignore_path.exists() {
-        return Err("No .gitignore file found in the repository root.".to_string());
+        return Err(Error::new(
+            ErrorCode::Config,
+            "No .gitignore file found in the repository root.",
+        ));
     }
 
-    println!("Reading .gitignore and suggesting patterns for .gwtconfig...");
+    output::text("Reading .gitignore and suggesting patterns for .gwtconfig...");
 
     let gitignore_content = fs::read_to_string(&gitignore_path)
         .map_err(|e| format!("Failed to read .gitignore: {e}"))?;
@@ -232,10 +996,9 @@ pub fn init_gwtconfig() -> Result<(), String> {
             continue;
         }
 
-        print!("Should '{trimmed_line}' be added to .gwtconfig? (y/N): ");
-        io::stdout()
-            .flush()
-            .map_err(|e| format!("Failed to flush stdout: {e}"))?;
+        output::prompt(format!(
+            "Should '{trimmed_line}' be added to .gwtconfig? (y/N): "
+        ))?;
 
         let mut answer = String::new();
         io::stdin()
@@ -247,8 +1010,9 @@ pub fn init_gwtconfig() -> Result<(), String> {
         }
     }
 
+    output::field("selected", &selected_items);
     if selected_items.is_empty() {
-        println!("{} No items selected for .gwtconfig.", "Info:".green());
+        output::info("No items selected for .gwtconfig.");
         return Ok(());
     }
 
@@ -256,37 +1020,897 @@ pub fn init_gwtconfig() -> Result<(), String> {
     fs::write(&gwtconfig_path, content_to_write)
         .map_err(|e| format!("Failed to write .gwtconfig: {e}"))?;
 
-    println!(
+    output::created(&gwtconfig_path);
+    output::text(format!(
         "{} .gwtconfig created/updated at {}.",
         "Success:".green(),
         gwtconfig_path.display()
-    );
+    ));
 
     Ok(())
 }
 
-pub fn remove_worktree(branch_name: &str) -> Result<(), String> {
+/// Flags of `gwt remove`.
+pub struct RemoveOptions {
+    /// Remove even if work would be lost
+    pub force: bool,
+    /// Only remove the directory, keep the branch
+    pub keep_branch: bool,
+}
+
+/// A removed worktree, as reported in `--json` mode.
+#[derive(Serialize)]
+struct Removed {
+    name: String,
+    path: PathBuf,
+    branch: Option<String>,
+    deleted_branch: bool,
+    deleted_ref: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RemoveFailure {
+    target: String,
+    error: Error,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    problems: Vec<Problem>,
+}
+
+/// Removes the worktrees given by branch name, registered name or path (picked interactively if
+/// there are none) and reports which removals failed.
+pub fn remove_worktrees(targets: &[String], options: &RemoveOptions) -> Result<(), Error> {
     let current_dir =
         env::current_dir().map_err(|e| format!("couldn't get current directory: {e}"))?;
+    let config = config::load(&get_git_root()?)?;
+    let repo_root = get_repo_root()?;
+    // The current directory may be one of the worktrees that are removed.
+    env::set_current_dir(&repo_root)
+        .map_err(|e| format!("Failed to change to the repository root: {e}"))?;
 
-    env::set_current_dir(worktree_path(branch_name)?)
-        .map_err(|e| format!("Failed to change to branch directory: {e}"))?;
+    let picked;
+    let targets = if targets.is_empty() {
+        picked = pick_worktree("remove")?.path.display().to_string();
+        vec![picked.as_str()]
+    } else {
+        targets.iter().map(String::as_str).collect()
+    };
 
-    let has_changes = git_utils::branch_has_changes()?;
+    let mut removed = Vec::new();
+    let mut failed = Vec::new();
+    for target in &targets {
+        let mut problems = Vec::new();
+        let removal = resolve_worktree(target, &current_dir).and_then(|worktree| {
+            remove_target(&config, &repo_root, worktree, options, &mut problems)
+        });
+        match removal {
+            Ok(removal) => {
+                output::info(format!(
+                    "Removed worktree {} ({})",
+                    removal.name.green(),
+                    removal.path.display()
+                ));
+                removed.push(removal);
+            }
+            Err(error) => failed.push(RemoveFailure {
+                target: target.to_string(),
+                error,
+                problems,
+            }),
+        }
+    }
 
-    if has_changes {
-        return Err(format!(
-            "The branch {} has changes and cannot be safely removed",
-            branch_name.green()
+    if removed.iter().any(|r| current_dir.starts_with(&r.path)) {
+        shell::change_directory(&repo_root)?;
+    }
+
+    output::field("removed", &removed);
+    if failed.is_empty() {
+        return Ok(());
+    }
+    output::field("failed", &failed);
+    if targets.len() == 1 {
+        return Err(failed.remove(0).error);
+    }
+
+    let mut message = format!(
+        "Removed {} of {} worktrees, failed to remove:",
+        removed.len(),
+        targets.len()
+    );
+    for failure in &failed {
+        let reason = failure.error.to_string().replace('\n', "\n    ");
+        message.push_str(&format!("\n  - {}: {reason}", failure.target));
+    }
+    Err(Error::new(failed[0].error.code, message))
+}
+
+/// Finds a worktree by registered name or branch, or else by its path relative to `current_dir`.
+fn resolve_worktree(target: &str, current_dir: &Path) -> Result<git_utils::Worktree, Error> {
+    if let Some(worktree) = registry::find_worktree(target)?.filter(|w| w.path.exists()) {
+        return Ok(worktree);
+    }
+    if let Some(worktree) = registry::find_worktree_at(&current_dir.join(target))? {
+        return Ok(worktree);
+    }
+    Err(Error::new(
+        ErrorCode::NotFound,
+        format!("No worktree found for {}", target.green()),
+    ))
+}
+
+fn remove_target(
+    config: &config::Config,
+    repo_root: &Path,
+    worktree: git_utils::Worktree,
+    options: &RemoveOptions,
+    problems: &mut Vec<Problem>,
+) -> Result<Removed, Error> {
+    if worktree.main || worktree.bare {
+        return Err(Error::new(
+            ErrorCode::Other,
+            format!(
+                "{} is the main worktree and can't be removed",
+                worktree.path.display()
+            ),
         ));
     }
+    let path = worktree.path.clone();
+    let mut registry = Registry::load()?;
+    let registered_name = registry.name_at(&path).map(str::to_string);
+    // Detached worktrees are known by the ref they were created from.
+    let name = match (&worktree.branch, &registered_name) {
+        (Some(branch), _) => branch.clone(),
+        (None, Some(registered_name)) => registered_name.clone(),
+        (None, None) => path.display().to_string(),
+    };
+    let delete_branch = worktree.branch.is_some() && !options.keep_branch;
+
+    *problems = safety::check_worktree(config, &worktree, delete_branch)?;
+    if !problems.is_empty() {
+        if !options.force {
+            return Err(Error::new(
+                ErrorCode::WorktreeDirty,
+                safety::describe(
+                    &format!("Removing the worktree {} would lose work:", name.green()),
+                    problems,
+                ),
+            ));
+        }
+        for problem in problems.iter() {
+            output::warn(format!("Removing {name} anyway: {}", problem.message));
+        }
+    }
 
-    env::set_current_dir(current_dir)
-        .map_err(|e| format!("Failed to change back to current directory: {e}"))?;
+    let context = HookContext {
+        branch: worktree.branch.as_deref(),
+        worktree: &path,
+        source_worktree: None,
+        repo_root,
+    };
+    run_hook(config, Hook::PreRemove, &path, &context)?;
 
-    git_utils::remove_worktree(&dirname(branch_name))?;
+    // git refuses to remove untracked files, which gwt's own links are unless ignored.
+    for link in safety::managed_links(config, &worktree)? {
+        file_ops::remove_path(&path.join(link))?;
+    }
+    // Fails if git refused or left the directory behind; the branch and registry entry are only
+    // touched once the worktree is really gone.
+    git_utils::remove_worktree(&path, options.force)?;
+
+    let record = registered_name.and_then(|name| registry.remove(&name));
+    if record.is_some() {
+        registry.save()?;
+    }
+    let pull_request_ref = record.and_then(|r| r.pull_request).map(pull_request_ref);
 
-    git_utils::delete_branch(branch_name)?;
+    match &worktree.branch {
+        // The safety checks made sure every commit of the branch is on a remote, another branch
+        // or a fetched pull request (or --force was given), so it doesn't need to be merged.
+        Some(branch) if delete_branch => git_utils::delete_branch(branch, true)?,
+        Some(branch) => output::info(format!("Keeping branch {}.", branch.green())),
+        None => output::info(format!(
+            "{} is a detached worktree, there is no branch to delete.",
+            name.green()
+        )),
+    }
+
+    if let Some(fetched) = &pull_request_ref {
+        git_utils::delete_ref(fetched)?;
+    }
 
+    run_hook(config, Hook::PostRemove, repo_root, &context)?;
+
+    Ok(Removed {
+        name,
+        path,
+        branch: worktree.branch,
+        deleted_branch: delete_branch,
+        deleted_ref: pull_request_ref,
+    })
+}
+
+/// Flags of `gwt prune`.
+pub struct PruneOptions {
+    /// Also consider worktrees unused for this many days
+    pub stale_days: Option<u64>,
+    /// Remove without asking
+    pub yes: bool,
+    /// Only show the candidates
+    pub dry_run: bool,
+}
+
+/// A worktree `gwt prune` would remove, as reported in `--json` mode.
+#[derive(Serialize)]
+struct PruneCandidate {
+    name: String,
+    path: PathBuf,
+    branch: Option<String>,
+    reasons: Vec<String>,
+    /// Seconds since the Unix epoch
+    last_activity: Option<u64>,
+    /// What would be lost; candidates with problems are not removed
+    problems: Vec<Problem>,
+}
+
+/// Finds worktrees whose branch is merged into the default branch, whose upstream is gone or
+/// which have not been used for `stale_days`, and removes them after confirmation.
+pub fn prune_worktrees(options: &PruneOptions) -> Result<(), Error> {
+    let git_root = get_git_root()?;
+    let config = config::load(&git_root)?;
+    let registry = Registry::load()?;
+
+    let remote = &config.remote.name;
+    let default_branch = get_default_branch(remote).ok();
+    let merge_target = match &default_branch {
+        Some(default_branch) => [format!("{remote}/{default_branch}"), default_branch.clone()]
+            .into_iter()
+            .find(|target| matches!(git_utils::resolve_commit(target), Ok(Some(_)))),
+        None => None,
+    };
+    if merge_target.is_none() {
+        output::warn(format!(
+            "Could not determine the default branch of {remote}, not checking for merged branches"
+        ));
+    }
+
+    let now = SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+
+    let mut candidates = Vec::new();
+    for worktree in get_worktrees()? {
+        if worktree.main || worktree.bare || worktree.prunable.is_some() {
+            continue;
+        }
+        if worktree.branch.is_some() && worktree.branch == default_branch {
+            continue;
+        }
+
+        let mut reasons = Vec::new();
+        if let Some(branch) = &worktree.branch {
+            // A new branch without commits of its own is trivially contained in its base.
+            let start_commit = registry
+                .name_at(&worktree.path)
+                .and_then(|name| registry.worktrees.get(name))
+                .and_then(|record| record.start_commit.as_deref());
+            let untouched = match start_commit {
+                Some(start_commit) => {
+                    git_utils::resolve_commit(&format!("refs/heads/{branch}"))?.as_deref()
+                        == Some(start_commit)
+                }
+                None => false,
+            };
+            if let Some(target) = merge_target.as_ref().filter(|_| !untouched) {
+                if git_utils::is_ancestor(&format!("refs/heads/{branch}"), target)? {
+                    reasons.push(format!("merged into {target}"));
+                }
+            }
+            if git_utils::upstream_gone(branch)? {
+                reasons.push("upstream gone".to_string());
+            }
+        }
+        let last_activity = git_utils::last_activity(&worktree.path)?;
+        if let (Some(stale_days), Some(last_activity)) = (options.stale_days, last_activity) {
+            let idle_days = now.saturating_sub(last_activity) / (24 * 60 * 60);
+            if idle_days >= stale_days {
+                reasons.push(format!("unused for {idle_days} days"));
+            }
+        }
+        if reasons.is_empty() {
+            continue;
+        }
+
+        let name = match (&worktree.branch, registry.name_at(&worktree.path)) {
+            (Some(branch), _) => branch.clone(),
+            (None, Some(name)) => name.to_string(),
+            (None, None) => worktree.path.display().to_string(),
+        };
+        candidates.push(PruneCandidate {
+            name,
+            problems: safety::check_worktree(&config, &worktree, worktree.branch.is_some())?,
+            path: worktree.path,
+            branch: worktree.branch,
+            reasons,
+            last_activity,
+        });
+    }
+
+    output::field("candidates", &candidates);
+    if candidates.is_empty() {
+        output::info("No worktrees to prune.");
+        return Ok(());
+    }
+
+    if !output::is_json() {
+        let name_w = candidates
+            .iter()
+            .map(|c| c.name.chars().count())
+            .max()
+            .unwrap_or(0);
+        let reasons = candidates
+            .iter()
+            .map(|c| c.reasons.join(", "))
+            .collect::<Vec<_>>();
+        let reasons_w = reasons.iter().map(|r| r.chars().count()).max().unwrap_or(0);
+        for (candidate, reasons) in candidates.iter().zip(&reasons) {
+            let state = if candidate.problems.is_empty() {
+                "remove".green()
+            } else {
+                "keep  ".red()
+            };
+            println!(
+                "  {} {:<reasons_w$} {state} {}",
+                format!("{:<name_w$}", candidate.name).green(),
+                reasons,
+                candidate.path.display()
+            );
+            for problem in &candidate.problems {
+                println!("      - {}", problem.message);
+            }
+        }
+    }
+
+    let removable = candidates
+        .iter()
+        .filter(|c| c.problems.is_empty())
+        .map(|c| c.path.display().to_string())
+        .collect::<Vec<_>>();
+    if removable.is_empty() {
+        output::info("Nothing can be removed without losing work. Use 'gwt remove --force' to remove them anyway.");
+        return Ok(());
+    }
+
+    let confirmed = if options.dry_run {
+        false
+    } else if options.yes {
+        true
+    } else if io::stdin().is_terminal() {
+        output::prompt(format!("Remove {} worktree(s)? (y/N): ", removable.len()))?;
+        let mut answer = String::new();
+        io::stdin()
+            .read_line(&mut answer)
+            .map_err(|e| format!("Failed to read line: {e}"))?;
+        answer.trim().eq_ignore_ascii_case("y")
+    } else {
+        false
+    };
+
+    output::field("dry_run", !confirmed);
+    if !confirmed {
+        output::info(format!(
+            "Dry run, nothing was removed. Run 'gwt prune --yes' to remove {} worktree(s).",
+            removable.len()
+        ));
+        return Ok(());
+    }
+
+    remove_worktrees(
+        &removable,
+        &RemoveOptions {
+            force: false,
+            keep_branch: false,
+        },
+    )
+}
+
+/// Renames the branch of a worktree and moves the worktree to where `[layout]` puts the new
+/// name. Symlinks to managed files in the old location are re-targeted.
+pub fn rename_worktree(old_name: &str, new_name: &str, update_upstream: bool) -> Result<(), Error> {
+    let current_dir =
+        env::current_dir().map_err(|e| format!("couldn't get current directory: {e}"))?;
+    let config = config::load(&get_git_root()?)?;
+    let repo_root = get_repo_root()?;
+    // The current directory may be the worktree that is moved.
+    env::set_current_dir(&repo_root)
+        .map_err(|e| format!("Failed to change to the repository root: {e}"))?;
+
+    let worktree = registry::find_worktree(old_name)?.ok_or_else(|| {
+        Error::new(
+            ErrorCode::NotFound,
+            format!("No worktree found for '{old_name}'"),
+        )
+    })?;
+    if worktree.main || worktree.bare {
+        return Err(Error::new(
+            ErrorCode::Other,
+            format!(
+                "'{old_name}' is checked out in the main worktree, which can't be moved. Use 'git branch -m' to rename the branch."
+            ),
+        ));
+    }
+    if worktree.locked.is_some() {
+        return Err(Error::new(
+            ErrorCode::Locked,
+            format!(
+                "The worktree {} is locked and can't be moved",
+                worktree.path.display()
+            ),
+        ));
+    }
+    if worktree.branch.is_some() && git_utils::local_branch_exists(new_name)? {
+        return Err(Error::new(
+            ErrorCode::AlreadyExists,
+            format!("Branch '{new_name}' already exists"),
+        ));
+    }
+
+    let old_path = worktree.path.clone();
+    let new_path = layout::worktree_path(&config, new_name)?;
+    let moves = new_path != old_path;
+    if moves {
+        registry::check_collision(new_name, &new_path)?;
+    }
+
+    if let Some(branch) = &worktree.branch {
+        git_utils::rename_branch(branch, new_name)?;
+        output::info(format!(
+            "Renamed branch {} to {}",
+            branch.green(),
+            new_name.green()
+        ));
+    }
+
+    if moves {
+        if let Err(error) = move_worktree(&old_path, &new_path) {
+            // Leave the branch as it was, so the worktree and its branch still match.
+            if let Some(branch) = &worktree.branch {
+                if let Err(undo) = git_utils::rename_branch(new_name, branch) {
+                    output::warn(format!(
+                        "Could not rename branch {new_name} back to {branch}: {undo}"
+                    ));
+                }
+            }
+            return Err(error);
+        }
+        output::info(format!(
+            "Moved worktree from {} to {}",
+            old_path.display(),
+            new_path.display()
+        ));
+    }
+    let new_path = fs::canonicalize(&new_path).unwrap_or(new_path);
+
+    let mut registry = Registry::load()?;
+    let registered_name = registry.name_at(&old_path).unwrap_or(old_name).to_string();
+    registry.rename(&registered_name, new_name, &new_path);
+    registry.save()?;
+
+    let retargeted = retarget_links(&old_path, &new_path)?;
+    if !retargeted.is_empty() {
+        output::info(format!(
+            "Updated {} symlink(s) pointing into the old worktree",
+            retargeted.len()
+        ));
+    }
+    shell::worktree_moved(&old_path, &new_path)?;
+
+    if let Some(branch) = &worktree.branch {
+        update_tracking(branch, new_name, update_upstream)?;
+    }
+
+    if current_dir.starts_with(&old_path) {
+        env::set_current_dir(&new_path)
+            .map_err(|e| format!("Failed to change to {}: {e}", new_path.display()))?;
+        shell::change_directory(&new_path)?;
+    }
+
+    output::field("renamed_from", old_name);
+    if worktree.branch.is_some() {
+        output::field("branch", new_name);
+    }
+    output::field("worktree", &new_path);
+    output::field("retargeted", &retargeted);
+    output::text(new_path.display());
+    Ok(())
+}
+
+fn move_worktree(old_path: &Path, new_path: &Path) -> Result<(), Error> {
+    if let Some(parent) = new_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create directory {}: {e}", parent.display()))?;
+    }
+    // An empty directory passed check_collision, but git would move the worktree into it.
+    if new_path.is_dir() {
+        fs::remove_dir(new_path)
+            .map_err(|e| format!("Failed to remove directory {}: {e}", new_path.display()))?;
+    }
+    layout::exclude_if_nested(new_path)?;
+    git_utils::move_worktree(old_path, new_path)
+}
+
+/// Points the symlinks to managed files in every worktree that lead below `from` at `to`. Each
+/// worktree's own config decides which files are managed there.
+fn retarget_links(from: &Path, to: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut retargeted = Vec::new();
+    for worktree in get_worktrees()?
+        .iter()
+        .filter(|w| !w.bare && w.prunable.is_none())
+    {
+        let config = config::load(&worktree.path)?;
+        for item in patterns::expand(&worktree.path, &config.files)? {
+            let link = worktree.path.join(&item.path);
+            if file_ops::retarget_symlink(&link, from, to)? {
+                retargeted.push(link);
+            }
+        }
+    }
+    Ok(retargeted)
+}
+
+/// After renaming `old_name` to `new_name`, the branch still tracks the old remote branch.
+/// With `update`, it tracks `new_name` on the same remote instead.
+fn update_tracking(old_name: &str, new_name: &str, update: bool) -> Result<(), Error> {
+    let Some((remote, remote_branch)) = git_utils::tracking(new_name)? else {
+        return Ok(());
+    };
+    if remote == "." {
+        return Ok(());
+    }
+    if !update {
+        if remote_branch == old_name {
+            output::info(format!(
+                "{} still tracks {remote}/{remote_branch}. Use --update-upstream to track {remote}/{new_name} instead.",
+                new_name.green()
+            ));
+        }
+        return Ok(());
+    }
+
+    git_utils::set_tracking(new_name, &remote, new_name)?;
+    let upstream = format!("{remote}/{new_name}");
+    if git_utils::resolve_commit(&format!("refs/remotes/{upstream}"))?.is_some() {
+        output::info(format!("{} now tracks {upstream}", new_name.green()));
+    } else {
+        output::info(format!(
+            "{} now tracks {upstream}, which doesn't exist yet. Push the branch to create it.",
+            new_name.green()
+        ));
+    }
+    output::field("upstream", &upstream);
+    Ok(())
+}
+
+pub fn list_worktrees() -> Result<(), Error> {
+    let current_root = get_git_root().ok().and_then(|p| fs::canonicalize(p).ok());
+    let worktrees = get_worktrees()?;
+    let registry = Registry::load()?;
+
+    struct Row {
+        current: bool,
+        name: String,
+        head: String,
+        tracking: String,
+        state: String,
+        flags: String,
+        path: String,
+    }
+
+    #[derive(Serialize)]
+    struct Status<'a> {
+        #[serde(flatten)]
+        worktree: &'a git_utils::Worktree,
+        /// Name in the gwt registry, e.g. the tag a detached worktree was created from
+        name: Option<&'a str>,
+        current: bool,
+        ahead: Option<usize>,
+        behind: Option<usize>,
+        dirty: Option<bool>,
+    }
+
+    let mut rows = Vec::new();
+    let mut statuses = Vec::new();
+    for worktree in &worktrees {
+        let current = current_root.as_deref() == fs::canonicalize(&worktree.path).ok().as_deref();
+
+        let registered_name = registry.name_at(&worktree.path);
+        let name = match (&worktree.branch, registered_name) {
+            (Some(branch), _) => branch.clone(),
+            (None, _) if worktree.bare => "(bare)".to_string(),
+            (None, Some(registered_name)) => format!("({registered_name}, detached)"),
+            (None, None) => "(detached)".to_string(),
+        };
+
+        let head = worktree
+            .head
+            .as_deref()
+            .map(|h| h.chars().take(7).collect())
+            .unwrap_or_default();
+
+        let ahead_behind = match &worktree.branch {
+            Some(branch) => git_utils::ahead_behind(branch)?,
+            None => None,
+        };
+        let tracking = match &worktree.branch {
+            Some(_) => match ahead_behind {
+                Some((0, 0)) => "up to date".to_string(),
+                Some((ahead, behind)) => format!("↑{ahead} ↓{behind}"),
+                None => "no upstream".to_string(),
+            },
+            None => "-".to_string(),
+        };
+
+        let dirty = if worktree.bare || worktree.prunable.is_some() {
+            None
+        } else {
+            Some(git_utils::worktree_has_changes(&worktree.path)?)
+        };
+        let state = match dirty {
+            Some(true) => "dirty".to_string(),
+            Some(false) => "clean".to_string(),
+            None => "-".to_string(),
+        };
+
+        statuses.push(Status {
+            worktree,
+            name: registered_name,
+            current,
+            ahead: ahead_behind.map(|(ahead, _)| ahead),
+            behind: ahead_behind.map(|(_, behind)| behind),
+            dirty,
+        });
+
+        let mut flags = Vec::new();
+        if worktree.main {
+            flags.push("main".to_string());
+        }
+        if worktree.bare {
+            flags.push("bare".to_string());
+        }
+        if let Some(reason) = &worktree.locked {
+            flags.push(flag_with_reason("locked", reason));
+        }
+        if let Some(reason) = &worktree.prunable {
+            flags.push(flag_with_reason("prunable", reason));
+        }
+
+        rows.push(Row {
+            current,
+            name,
+            head,
+            tracking,
+            state,
+            flags: flags.join(","),
+            path: worktree.path.display().to_string(),
+        });
+    }
+
+    if output::is_json() {
+        output::field("worktrees", &statuses);
+        return Ok(());
+    }
+
+    let width =
+        |f: fn(&Row) -> &String| rows.iter().map(|r| f(r).chars().count()).max().unwrap_or(0);
+    let name_w = width(|r| &r.name);
+    let tracking_w = width(|r| &r.tracking);
+    let state_w = width(|r| &r.state);
+    let flags_w = width(|r| &r.flags);
+
+    for row in &rows {
+        let name = format!("{:<name_w$}", row.name);
+        let name = if row.current {
+            name.green().bold()
+        } else if row.name.starts_with('(') {
+            name.yellow()
+        } else {
+            name.green()
+        };
+        let state = format!("{:<state_w$}", row.state);
+        let state = match row.state.as_str() {
+            "dirty" => state.red(),
+            "clean" => state.green(),
+            _ => state.dimmed(),
+        };
+        println!(
+            "{} {name} {:<7} {:<tracking_w$} {state} {} {}",
+            if row.current { "*" } else { " " },
+            row.head.dimmed(),
+            row.tracking,
+            format!("{:<flags_w$}", row.flags).cyan(),
+            row.path
+        );
+    }
+
+    Ok(())
+}
+
+fn flag_with_reason(flag: &str, reason: &str) -> String {
+    if reason.is_empty() {
+        flag.to_string()
+    } else {
+        format!("{flag}({reason})")
+    }
+}
+
+pub fn shell_init(shell: Shell) -> Result<(), Error> {
+    let script = shell::init_script(shell);
+    if output::is_json() {
+        output::field("script", script);
+    } else {
+        print!("{script}");
+    }
+    Ok(())
+}
+
+pub fn switch_worktree(target: Option<&str>) -> Result<(), Error> {
+    let Some(target) = target else {
+        let worktree = pick_worktree("switch")?;
+        shell::change_directory(&worktree.path)?;
+        output::field("worktree", &worktree.path);
+        output::text(worktree.path.display());
+        return Ok(());
+    };
+
+    let path = if target == "-" {
+        let previous = shell::previous_worktree()?.ok_or_else(|| {
+            Error::new(
+                ErrorCode::NotFound,
+                "There is no previous worktree to switch to",
+            )
+        })?;
+        Some(PathBuf::from(previous))
+    } else {
+        registry::find_worktree(target)?.map(|w| w.path)
+    };
+
+    let Some(path) = path.filter(|p| p.is_dir()) else {
+        return Err(Error::new(
+            ErrorCode::NotFound,
+            format!("No worktree found for {}", target.green()),
+        ));
+    };
+    let path = fs::canonicalize(&path).map_err(|e| {
+        format!(
+            "Failed to canonicalize worktree path '{}': {e}",
+            path.display()
+        )
+    })?;
+
+    shell::change_directory(&path)?;
+
+    output::field("worktree", &path);
+    output::text(path.display());
+    Ok(())
+}
+
+pub fn pick(command: &[String]) -> Result<(), Error> {
+    let worktree = pick_worktree("pick")?;
+
+    let Some((program, args)) = command.split_first() else {
+        output::field("worktree", &worktree.path);
+        output::field("branch", &worktree.branch);
+        output::text(worktree.path.display());
+        return Ok(());
+    };
+
+    let status = Command::new(program)
+        .args(args)
+        .current_dir(&worktree.path)
+        .stdout(output::child_stdout())
+        .status()
+        .map_err(|e| format!("Failed to execute {program}: {e}"))?;
+
+    output::field("worktree", &worktree.path);
+    output::field("exit_code", status.code());
+    if !status.success() {
+        return Err(format!("'{}' exited with {status}", command.join(" ")).into());
+    }
+    Ok(())
+}
+
+pub fn migrate_config() -> Result<(), Error> {
+    let git_root = get_git_root()?;
+    let legacy_path = git_root.join(LEGACY_CONFIG_FILE);
+    let toml_path = git_root.join(TOML_CONFIG_FILE);
+
+    if !legacy_path.exists() {
+        return Err(Error::new(
+            ErrorCode::NotFound,
+            format!("No {LEGACY_CONFIG_FILE} found in {}", git_root.display()),
+        ));
+    }
+    if toml_path.exists() {
+        return Err(Error::new(
+            ErrorCode::Config,
+            format!(
+                "{TOML_CONFIG_FILE} already exists in {}",
+                git_root.display()
+            ),
+        ));
+    }
+
+    let legacy_content = fs::read_to_string(&legacy_path)
+        .map_err(|e| format!("Failed to read {LEGACY_CONFIG_FILE}: {e}"))?;
+    fs::write(&toml_path, config::migrate_legacy_config(&legacy_content))
+        .map_err(|e| format!("Failed to write {TOML_CONFIG_FILE}: {e}"))?;
+    // A committed .gwtconfig is still what other branches and worktrees read, and deleting it
+    // would leave a deletion to commit, so it is kept. .gwt.toml takes precedence over it.
+    let tracked = git_utils::is_tracked(&git_root, LEGACY_CONFIG_FILE)?;
+    if tracked {
+        output::warn(format!(
+            "{LEGACY_CONFIG_FILE} is tracked by git, keeping it. {TOML_CONFIG_FILE} takes precedence; run 'git rm {LEGACY_CONFIG_FILE}' once no branch needs it anymore."
+        ));
+    } else {
+        fs::remove_file(&legacy_path)
+            .map_err(|e| format!("Failed to remove {LEGACY_CONFIG_FILE}: {e}"))?;
+    }
+
+    output::created(&toml_path);
+    if tracked {
+        output::field("kept", &legacy_path);
+    } else {
+        output::field("removed", &legacy_path);
+    }
+    output::text(format!(
+        "{} Migrated {} to {}.",
+        "Success:".green(),
+        legacy_path.display(),
+        toml_path.display()
+    ));
+    Ok(())
+}
+
+pub fn show_config(origin: bool) -> Result<(), Error> {
+    let git_root = get_git_root()?;
+    let config = config::load(&git_root)?;
+    let settings = config.settings();
+
+    #[derive(Serialize)]
+    struct Setting<'a> {
+        key: &'a str,
+        value: &'a toml::Value,
+        origin: Option<&'a Path>,
+    }
+
+    if output::is_json() {
+        let settings: Vec<Setting> = settings
+            .iter()
+            .map(|(key, value, origin)| Setting {
+                key,
+                value,
+                origin: *origin,
+            })
+            .collect();
+        output::field("global_config", config::global_config_path());
+        output::field("settings", settings);
+        return Ok(());
+    }
+
+    let lines: Vec<String> = settings
+        .iter()
+        .map(|(key, value, _)| format!("{key} = {value}"))
+        .collect();
+    let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    for (line, (_, _, source)) in lines.iter().zip(&settings) {
+        if origin {
+            let source = match source {
+                Some(path) => path.display().to_string(),
+                None => "default".to_string(),
+            };
+            println!("{line:<width$}  {}", format!("# {source}").dimmed());
+        } else {
+            println!("{line}");
+        }
+    }
     Ok(())
 }
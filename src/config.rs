@@ -1,15 +1,662 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-pub fn get_files_from_config(config_path: &Path) -> Result<Vec<String>, String> {
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, ErrorCode};
+use crate::output;
+
+/// How a managed file gets into a worktree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Symlink to the source worktree; all worktrees share the file
+    Link,
+    /// Independent copy that `gwt sync` keeps up to date
+    Copy,
+    /// Copied once when the worktree is created and never touched again
+    Template,
+}
+
+/// A single line of `.gwtconfig`: a path or pattern with an optional `link:`, `copy:`,
+/// `template:` or `skip:` prefix. `skip:` is the same as a `!` negation.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub pattern: String,
+    /// Explicit mode of the entry; `None` uses the default mode of the command
+    pub mode: Option<Mode>,
+    /// The config file the entry was read from
+    pub origin: Option<PathBuf>,
+}
+
+impl Entry {
+    pub fn parse(line: &str) -> Entry {
+        let prefixes = [
+            ("link:", Some(Mode::Link)),
+            ("copy:", Some(Mode::Copy)),
+            ("template:", Some(Mode::Template)),
+        ];
+        for (prefix, mode) in prefixes {
+            if let Some(pattern) = line.strip_prefix(prefix) {
+                return Entry {
+                    pattern: pattern.trim().to_string(),
+                    mode,
+                    origin: None,
+                };
+            }
+        }
+        if let Some(pattern) = line.strip_prefix("skip:") {
+            return Entry {
+                pattern: format!("!{}", pattern.trim()),
+                mode: None,
+                origin: None,
+            };
+        }
+        Entry {
+            pattern: line.to_string(),
+            mode: None,
+            origin: None,
+        }
+    }
+
+    fn with_origin(mut self, origin: &Path) -> Entry {
+        self.origin = Some(origin.to_path_buf());
+        self
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            Some(Mode::Link) => write!(f, "link:{}", self.pattern),
+            Some(Mode::Copy) => write!(f, "copy:{}", self.pattern),
+            Some(Mode::Template) => write!(f, "template:{}", self.pattern),
+            None => write!(f, "{}", self.pattern),
+        }
+    }
+}
+
+/// Effective settings: built-in defaults, overridden by the user-level global config, overridden
+/// by the repository config (`.gwt.toml` or the legacy `.gwtconfig`).
+#[derive(Debug, Default)]
+pub struct Config {
+    /// Managed files, in the same syntax as the lines of `.gwtconfig`. Global entries come first,
+    /// so repository entries win when both match a path.
+    pub files: Vec<Entry>,
+    /// The worktree holding the real copies of linked files, as a branch, registered name or path;
+    /// `None` uses the worktree of the default branch
+    pub primary: Option<String>,
+    pub defaults: Defaults,
+    pub remote: Remote,
+    pub layout: Layout,
+    pub review: Review,
+    /// Shell commands to run at points of the worktree lifecycle
+    pub hooks: BTreeMap<Hook, Vec<String>>,
+    /// The file each setting that is not a built-in default came from, keyed like `defaults.copy`
+    pub origins: BTreeMap<&'static str, PathBuf>,
+}
+
+/// Default values for command line flags.
+#[derive(Debug, Default)]
+pub struct Defaults {
+    pub copy: bool,
+    pub pull: bool,
+    pub verbose: bool,
+    pub fetch: bool,
+    /// Start point of new branches; `None` uses the default branch of the remote
+    pub base: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Remote {
+    /// The remote used to detect the default branch
+    pub name: String,
+}
+
+impl Default for Remote {
+    fn default() -> Self {
+        Remote {
+            name: "origin".to_string(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Layout {
+    /// Where new worktrees go, relative to the main worktree. Supports `{repo}`, `{branch}` and
+    /// `{branch_slug}`.
+    pub path: String,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            path: "../{branch_slug}".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Review {
+    /// Ref of a pull request on the remote with a `{number}` placeholder; `None` tries the GitHub
+    /// and GitLab refs
+    pub ref_pattern: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Hook {
+    PostAdd,
+    PreRemove,
+    PostRemove,
+    PostSync,
+    PostClone,
+}
+
+impl Hook {
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::PostAdd => "post-add",
+            Hook::PreRemove => "pre-remove",
+            Hook::PostRemove => "post-remove",
+            Hook::PostSync => "post-sync",
+            Hook::PostClone => "post-clone",
+        }
+    }
+
+    fn key(self) -> &'static str {
+        match self {
+            Hook::PostAdd => "hooks.post-add",
+            Hook::PreRemove => "hooks.pre-remove",
+            Hook::PostRemove => "hooks.post-remove",
+            Hook::PostSync => "hooks.post-sync",
+            Hook::PostClone => "hooks.post-clone",
+        }
+    }
+}
+
+/// One config file. Every setting is optional so files can be layered on top of each other.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TomlConfig {
+    files: TomlFiles,
+    defaults: TomlDefaults,
+    remote: TomlRemote,
+    layout: TomlLayout,
+    review: TomlReview,
+    hooks: TomlHooks,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct TomlHooks {
+    post_add: Option<HookCommands>,
+    pre_remove: Option<HookCommands>,
+    post_remove: Option<HookCommands>,
+    post_sync: Option<HookCommands>,
+    post_clone: Option<HookCommands>,
+}
+
+/// A hook is either a single command or a list of commands run in order.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HookCommands {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TomlFiles {
+    paths: Vec<String>,
+    primary: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TomlDefaults {
+    copy: Option<bool>,
+    pull: Option<bool>,
+    verbose: Option<bool>,
+    fetch: Option<bool>,
+    base: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TomlRemote {
+    name: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TomlLayout {
+    path: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TomlReview {
+    #[serde(rename = "ref")]
+    ref_pattern: Option<String>,
+}
+
+pub const TOML_CONFIG_FILE: &str = ".gwt.toml";
+pub const LEGACY_CONFIG_FILE: &str = ".gwtconfig";
+const GLOBAL_CONFIG_FILE: &str = "config.toml";
+
+impl Config {
+    fn apply(&mut self, layer: TomlConfig, origin: &Path) {
+        self.files.extend(
+            layer
+                .files
+                .paths
+                .iter()
+                .map(|p| p.trim())
+                .filter(|p| !p.is_empty())
+                .map(|p| Entry::parse(p).with_origin(origin)),
+        );
+
+        let mut set = |key: &'static str, is_set: bool| {
+            if is_set {
+                self.origins.insert(key, origin.to_path_buf());
+            }
+        };
+        set("files.primary", layer.files.primary.is_some());
+        set("defaults.copy", layer.defaults.copy.is_some());
+        set("defaults.pull", layer.defaults.pull.is_some());
+        set("defaults.verbose", layer.defaults.verbose.is_some());
+        set("defaults.fetch", layer.defaults.fetch.is_some());
+        set("defaults.base", layer.defaults.base.is_some());
+        set("remote.name", layer.remote.name.is_some());
+        set("layout.path", layer.layout.path.is_some());
+        set("review.ref", layer.review.ref_pattern.is_some());
+
+        let defaults = &mut self.defaults;
+        defaults.copy = layer.defaults.copy.unwrap_or(defaults.copy);
+        defaults.pull = layer.defaults.pull.unwrap_or(defaults.pull);
+        defaults.verbose = layer.defaults.verbose.unwrap_or(defaults.verbose);
+        defaults.fetch = layer.defaults.fetch.unwrap_or(defaults.fetch);
+        if let Some(primary) = layer.files.primary {
+            self.primary = Some(primary);
+        }
+        if let Some(base) = layer.defaults.base {
+            defaults.base = Some(base);
+        }
+        if let Some(name) = layer.remote.name {
+            self.remote.name = name;
+        }
+        if let Some(path) = layer.layout.path {
+            self.layout.path = path;
+        }
+        if let Some(ref_pattern) = layer.review.ref_pattern {
+            self.review.ref_pattern = Some(ref_pattern);
+        }
+
+        // A hook defined in the repository replaces the global hook of the same name.
+        let hooks = [
+            (Hook::PostAdd, layer.hooks.post_add),
+            (Hook::PreRemove, layer.hooks.pre_remove),
+            (Hook::PostRemove, layer.hooks.post_remove),
+            (Hook::PostSync, layer.hooks.post_sync),
+            (Hook::PostClone, layer.hooks.post_clone),
+        ];
+        for (hook, commands) in hooks {
+            let Some(commands) = commands else {
+                continue;
+            };
+            let commands = match commands {
+                HookCommands::One(command) => vec![command],
+                HookCommands::Many(commands) => commands,
+            };
+            self.origins.insert(hook.key(), origin.to_path_buf());
+            self.hooks.insert(hook, commands);
+        }
+    }
+
+    /// Every effective setting with the file it came from (`None` for built-in defaults).
+    pub fn settings(&self) -> Vec<(&'static str, toml::Value, Option<&Path>)> {
+        let origin = |key: &str| self.origins.get(key).map(PathBuf::as_path);
+        let mut settings = vec![
+            setting("defaults.copy", self.defaults.copy, origin("defaults.copy")),
+            setting("defaults.pull", self.defaults.pull, origin("defaults.pull")),
+            setting(
+                "defaults.verbose",
+                self.defaults.verbose,
+                origin("defaults.verbose"),
+            ),
+            setting(
+                "defaults.fetch",
+                self.defaults.fetch,
+                origin("defaults.fetch"),
+            ),
+            setting(
+                "remote.name",
+                self.remote.name.as_str(),
+                origin("remote.name"),
+            ),
+            setting(
+                "layout.path",
+                self.layout.path.as_str(),
+                origin("layout.path"),
+            ),
+        ];
+        if let Some(base) = &self.defaults.base {
+            settings.push(setting(
+                "defaults.base",
+                base.as_str(),
+                origin("defaults.base"),
+            ));
+        }
+        if let Some(primary) = &self.primary {
+            settings.push(setting(
+                "files.primary",
+                primary.as_str(),
+                origin("files.primary"),
+            ));
+        }
+        if let Some(ref_pattern) = &self.review.ref_pattern {
+            settings.push(setting(
+                "review.ref",
+                ref_pattern.as_str(),
+                origin("review.ref"),
+            ));
+        }
+        for (hook, commands) in &self.hooks {
+            for command in commands {
+                settings.push(setting(hook.key(), command.as_str(), origin(hook.key())));
+            }
+        }
+        for entry in &self.files {
+            settings.push(setting(
+                "files.paths",
+                entry.to_string(),
+                entry.origin.as_deref(),
+            ));
+        }
+        settings
+    }
+}
+
+fn setting<'a>(
+    key: &'static str,
+    value: impl Into<toml::Value>,
+    origin: Option<&'a Path>,
+) -> (&'static str, toml::Value, Option<&'a Path>) {
+    (key, value.into(), origin)
+}
+
+/// `$XDG_CONFIG_HOME/gwt/config.toml`, falling back to `~/.config/gwt/config.toml`.
+pub fn global_config_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("gwt").join(GLOBAL_CONFIG_FILE))
+}
+
+/// Loads the effective configuration for the worktree at `git_root`. In the repository,
+/// `.gwt.toml` takes precedence over the plain `.gwtconfig`.
+pub fn load(git_root: &Path) -> Result<Config, Error> {
+    load_from(global_config_path().as_deref(), git_root)
+}
+
+/// Layers the repository config of `git_root` on top of the global config at `global_path`.
+fn load_from(global_path: Option<&Path>, git_root: &Path) -> Result<Config, Error> {
+    let mut config = Config::default();
+
+    if let Some(global_path) = global_path.filter(|p| p.exists()) {
+        config.apply(read_toml(global_path)?, global_path);
+    }
+
+    let toml_path = git_root.join(TOML_CONFIG_FILE);
+    let legacy_path = git_root.join(LEGACY_CONFIG_FILE);
+
+    if !toml_path.exists() {
+        let entries = get_files_from_config(&legacy_path)?;
+        config
+            .files
+            .extend(entries.into_iter().map(|e| e.with_origin(&legacy_path)));
+        return Ok(config);
+    }
+
+    if legacy_path.exists() {
+        output::warn(format!(
+            "Both {TOML_CONFIG_FILE} and {LEGACY_CONFIG_FILE} exist, ignoring {LEGACY_CONFIG_FILE}. Run 'gwt config migrate' to merge them."
+        ));
+    }
+
+    config.apply(read_toml(&toml_path)?, &toml_path);
+    Ok(config)
+}
+
+fn read_toml(path: &Path) -> Result<TomlConfig, Error> {
+    let content = fs::read_to_string(path).map_err(|e| {
+        Error::new(
+            ErrorCode::Config,
+            format!("Failed to read {}: {e}", path.display()),
+        )
+    })?;
+    toml::from_str(&content).map_err(|e| {
+        Error::new(
+            ErrorCode::Config,
+            format!("Failed to parse {}: {e}", path.display()),
+        )
+    })
+}
+
+pub fn get_files_from_config(config_path: &Path) -> Result<Vec<Entry>, Error> {
     if !config_path.exists() {
         return Ok(Vec::new());
     }
-    let content =
-        fs::read_to_string(config_path).map_err(|e| format!("Failed to read .gwtconfig: {e}"))?;
+    let content = fs::read_to_string(config_path)
+        .map_err(|e| Error::new(ErrorCode::Config, format!("Failed to read .gwtconfig: {e}")))?;
     Ok(content
         .lines()
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty())
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty() && !s.starts_with('#'))
+        .map(Entry::parse)
         .collect())
 }
+
+/// Converts a legacy `.gwtconfig` into the `[files]` section of a `.gwt.toml`, keeping comments.
+pub fn migrate_legacy_config(legacy_content: &str) -> String {
+    let mut paths = String::new();
+    for line in legacy_content.lines().map(str::trim) {
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('#') {
+            paths.push_str(&format!("    {line}\n"));
+        } else {
+            paths.push_str(&format!("    {},\n", toml::Value::String(line.to_string())));
+        }
+    }
+    format!(
+        "# Managed files, one gitignore-style pattern per entry.\n\
+         # Prefix an entry with link:, copy:, template: or skip: to choose how it is managed.\n\
+         [files]\n\
+         paths = [\n{paths}]\n"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(toml: &str) -> TomlConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn patterns(config: &Config) -> Vec<String> {
+        config.files.iter().map(Entry::to_string).collect()
+    }
+
+    #[test]
+    fn repository_settings_override_global_ones() {
+        let global = Path::new("/home/me/.config/gwt/config.toml");
+        let repo = Path::new("/repo/.gwt.toml");
+        let mut config = Config::default();
+        config.apply(
+            layer(
+                r#"
+                [defaults]
+                copy = true
+                pull = true
+                [remote]
+                name = "upstream"
+                "#,
+            ),
+            global,
+        );
+        config.apply(
+            layer(
+                r#"
+                [defaults]
+                copy = false
+                [layout]
+                path = ".worktrees/{branch}"
+                "#,
+            ),
+            repo,
+        );
+
+        assert!(!config.defaults.copy);
+        assert!(config.defaults.pull);
+        assert!(!config.defaults.fetch);
+        assert_eq!(config.remote.name, "upstream");
+        assert_eq!(config.layout.path, ".worktrees/{branch}");
+        assert_eq!(config.defaults.base, None);
+    }
+
+    #[test]
+    fn origins_name_the_file_that_set_each_setting() {
+        let global = Path::new("/global.toml");
+        let repo = Path::new("/repo/.gwt.toml");
+        let mut config = Config::default();
+        config.apply(
+            layer("[defaults]\ncopy = true\npull = true\n[files]\npaths = [\".envrc\"]"),
+            global,
+        );
+        config.apply(
+            layer("[defaults]\ncopy = false\n[files]\npaths = [\".env\"]\nprimary = \"dev\""),
+            repo,
+        );
+
+        assert_eq!(config.origins["defaults.copy"], repo);
+        assert_eq!(config.origins["defaults.pull"], global);
+        assert_eq!(config.origins["files.primary"], repo);
+        assert!(!config.origins.contains_key("defaults.fetch"));
+        assert!(!config.origins.contains_key("remote.name"));
+
+        let files = config
+            .files
+            .iter()
+            .map(|e| (e.pattern.as_str(), e.origin.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(files, [(".envrc", Some(global)), (".env", Some(repo))]);
+
+        let settings = config.settings();
+        let origin = |key: &str| {
+            settings
+                .iter()
+                .find(|(k, _, _)| *k == key)
+                .and_then(|(_, _, origin)| *origin)
+        };
+        assert_eq!(origin("defaults.copy"), Some(repo));
+        assert_eq!(origin("defaults.fetch"), None);
+    }
+
+    #[test]
+    fn repository_hooks_replace_global_hooks_of_the_same_name() {
+        let global = Path::new("/global.toml");
+        let repo = Path::new("/repo/.gwt.toml");
+        let mut config = Config::default();
+        config.apply(
+            layer(
+                r#"
+                [hooks]
+                post-add = ["direnv allow", "npm ci"]
+                post-sync = "echo synced"
+                "#,
+            ),
+            global,
+        );
+        config.apply(layer("[hooks]\npost-add = \"make setup\""), repo);
+
+        assert_eq!(config.hooks[&Hook::PostAdd], ["make setup"]);
+        assert_eq!(config.hooks[&Hook::PostSync], ["echo synced"]);
+        assert!(!config.hooks.contains_key(&Hook::PreRemove));
+        assert_eq!(config.origins["hooks.post-add"], repo);
+        assert_eq!(config.origins["hooks.post-sync"], global);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(toml::from_str::<TomlConfig>("[defaults]\ncoppy = true").is_err());
+        assert!(toml::from_str::<TomlConfig>("[unknown]").is_err());
+    }
+
+    #[test]
+    fn gwt_toml_wins_over_gwtconfig() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join(LEGACY_CONFIG_FILE), "legacy.txt\n").unwrap();
+        fs::write(
+            root.path().join(TOML_CONFIG_FILE),
+            "[files]\npaths = [\"copy:.env\"]\n",
+        )
+        .unwrap();
+
+        let config = load_from(None, root.path()).unwrap();
+        assert_eq!(patterns(&config), ["copy:.env"]);
+    }
+
+    #[test]
+    fn gwtconfig_is_used_without_gwt_toml_and_layered_on_the_global_config() {
+        let root = tempfile::tempdir().unwrap();
+        let legacy = root.path().join(LEGACY_CONFIG_FILE);
+        fs::write(&legacy, "# comment\n\n.env\nskip:node_modules/.cache\n").unwrap();
+        let global = root.path().join("global.toml");
+        fs::write(
+            &global,
+            "[files]\npaths = [\".envrc\"]\n[defaults]\ncopy = true\n",
+        )
+        .unwrap();
+
+        let config = load_from(Some(&global), root.path()).unwrap();
+        assert_eq!(
+            patterns(&config),
+            [".envrc", ".env", "!node_modules/.cache"]
+        );
+        assert_eq!(config.files[1].origin.as_deref(), Some(legacy.as_path()));
+        assert!(config.defaults.copy);
+        assert_eq!(config.origins["defaults.copy"], global);
+    }
+
+    #[test]
+    fn missing_files_give_the_built_in_defaults() {
+        let root = tempfile::tempdir().unwrap();
+        let config = load_from(Some(&root.path().join("missing.toml")), root.path()).unwrap();
+        assert!(config.files.is_empty());
+        assert!(!config.defaults.copy);
+        assert_eq!(config.remote.name, "origin");
+        assert_eq!(config.layout.path, "../{branch_slug}");
+        assert!(config.origins.is_empty());
+    }
+
+    #[test]
+    fn migrated_config_has_the_same_entries() {
+        let legacy = "# secrets\n.env\n\nlink:cache/\nskip:cache/tmp\nweird \"name\"\n";
+        let migrated = migrate_legacy_config(legacy);
+        assert!(migrated.contains("    # secrets\n"));
+
+        let mut config = Config::default();
+        config.apply(layer(&migrated), Path::new("/repo/.gwt.toml"));
+        assert_eq!(
+            patterns(&config),
+            [".env", "link:cache/", "!cache/tmp", "weird \"name\""]
+        );
+    }
+}
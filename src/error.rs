@@ -0,0 +1,78 @@
+use serde::Serialize;
+use std::fmt;
+
+/// Stable, machine-readable error codes reported in `--json` mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCode {
+    NotARepository,
+    NotFound,
+    AlreadyExists,
+    Ambiguous,
+    GitFailed,
+    WorktreeDirty,
+    Locked,
+    BranchNotMerged,
+    Config,
+    HookFailed,
+    Conflict,
+    Io,
+    NotATerminal,
+    Cancelled,
+    Other,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Error {
+    pub code: ErrorCode,
+    pub message: String,
+    /// What git printed to stderr, if the error comes from a failed git command
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+}
+
+impl Error {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Error {
+            code,
+            message: message.into(),
+            stderr: None,
+        }
+    }
+
+    pub fn with_stderr(mut self, stderr: &[u8]) -> Self {
+        let stderr = String::from_utf8_lossy(stderr).trim().to_string();
+        self.stderr = Some(stderr).filter(|s| !s.is_empty());
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)?;
+        if let Some(stderr) = &self.stderr {
+            for line in stderr.lines() {
+                write!(f, "\n  {line}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::new(ErrorCode::Other, message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Error::new(ErrorCode::Other, message)
+    }
+}
+
+impl From<Error> for String {
+    fn from(error: Error) -> Self {
+        error.to_string()
+    }
+}
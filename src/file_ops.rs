@@ -1,148 +1,394 @@
-use colored::*;
 use std::fs;
 use std::os::unix::fs::symlink;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use walkdir::WalkDir;
 
-use crate::config::get_files_from_config;
-use crate::git_utils::get_git_root;
+use crate::config::{Entry, Mode};
+use crate::output::{self, Action};
+use crate::patterns;
+use crate::store::Store;
 
-pub fn link_files_from_config(worktree_path: &Path, git_root: &Path) -> Result<(), String> {
-    let config_path = git_root.join(".gwtconfig");
-    let files_to_link = get_files_from_config(&config_path)?;
-
-    if files_to_link.is_empty() {
-        println!(
-            "{} No .gwtconfig file found or it is empty. No files will be linked.",
-            "Info:".green()
-        );
+/// Puts every managed file of `source_root` into a new worktree and links the shared files to
+/// the store. Entries without an explicit `link:`, `copy:` or `template:` mode use
+/// `default_mode`.
+pub fn apply_files_from_config(
+    worktree_path: &Path,
+    source_root: &Path,
+    entries: &[Entry],
+    default_mode: Mode,
+    store: &Store,
+) -> Result<(), String> {
+    if entries.is_empty() && store.items.is_empty() {
+        output::info("No managed files configured. No files will be linked or copied.");
         return Ok(());
     }
+    link_shared(worktree_path, store)?;
 
-    for item_str in files_to_link {
-        let item_trimmed = item_str.trim_end_matches('/');
-        let src_path_abs = git_root.join(item_trimmed);
+    for item in patterns::expand(source_root, entries)? {
+        // Shared items always come from the store.
+        if store.covering(&item.path).is_some() {
+            continue;
+        }
+        let src_path_abs = source_root.join(&item.path);
+        let dest_path_abs = worktree_path.join(&item.path);
+
+        if src_path_abs.symlink_metadata().is_err() {
+            output::item(
+                Action::Skipped,
+                &item.path,
+                worktree_path,
+                format!("File or directory '{}' not found, skipping.", item.path),
+            );
+            continue;
+        }
 
-        if src_path_abs.exists() {
-            // Check if it's a broken symlink
-            if src_path_abs.is_symlink() && fs::metadata(&src_path_abs).is_err() {
-                eprintln!(
-                    "{} Source path '{}' is a broken symlink, skipping.",
-                    "Warning:".yellow(),
+        // Check if it's a broken symlink
+        if src_path_abs.is_symlink() && fs::metadata(&src_path_abs).is_err() {
+            output::item(
+                Action::Skipped,
+                &item.path,
+                worktree_path,
+                format!(
+                    "Source path '{}' is a broken symlink, skipping.",
                     src_path_abs.display()
-                );
-                continue;
-            }
+                ),
+            );
+            continue;
+        }
 
-            let dest_path_abs = worktree_path.join(item_trimmed);
+        let mode = item.mode.unwrap_or(default_mode);
+        if mode == Mode::Template && dest_path_abs.symlink_metadata().is_ok() {
+            output::item(
+                Action::Skipped,
+                &item.path,
+                worktree_path,
+                format!(
+                    "Template '{}' already exists in the new worktree, keeping it.",
+                    item.path
+                ),
+            );
+            continue;
+        }
 
-            if let Some(parent) = dest_path_abs.parent() {
-                fs::create_dir_all(parent)
-                    .map_err(|e| format!("Failed to create directory {}: {e}", parent.display()))?;
-            }
+        if let Some(parent) = dest_path_abs.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create directory {}: {e}", parent.display()))?;
+        }
+        remove_path(&dest_path_abs)?;
 
-            // Remove existing file/directory/symlink at destination if it exists
-            if dest_path_abs.symlink_metadata().is_ok() {
-                if dest_path_abs.is_dir() {
-                    fs::remove_dir_all(&dest_path_abs).map_err(|e| {
-                        format!(
-                            "Failed to remove directory {}: {e}",
-                            dest_path_abs.display()
-                        )
-                    })?;
-                } else {
-                    fs::remove_file(&dest_path_abs).map_err(|e| {
-                        format!("Failed to remove file {}: {e}", dest_path_abs.display())
-                    })?;
-                }
+        match mode {
+            Mode::Link => {
+                // Use absolute paths for symlink
+                symlink(&src_path_abs, &dest_path_abs).map_err(|e| {
+                    format!(
+                        "Failed to create symlink from {} to {}: {e}",
+                        src_path_abs.display(),
+                        dest_path_abs.display()
+                    )
+                })?;
+                output::item(
+                    Action::Linked,
+                    &item.path,
+                    worktree_path,
+                    format!("Linked '{}' to new worktree.", item.path),
+                );
+            }
+            Mode::Copy | Mode::Template => {
+                // Copy what a symlinked source points to, not the symlink.
+                let src_path_abs = fs::canonicalize(&src_path_abs)
+                    .map_err(|e| format!("Failed to resolve {}: {e}", src_path_abs.display()))?;
+                cp_cow(&src_path_abs, &dest_path_abs)?;
+                output::item(
+                    Action::Copied,
+                    &item.path,
+                    worktree_path,
+                    format!("Copied '{}' to new worktree.", item.path),
+                );
             }
-
-            // Use absolute paths for symlink
-            symlink(&src_path_abs, &dest_path_abs).map_err(|e| {
-                format!(
-                    "Failed to create symlink from {} to {}: {e}",
-                    src_path_abs.display(),
-                    dest_path_abs.display()
-                )
-            })?;
-            println!(
-                "{} Linked '{}' to new worktree.",
-                "Info:".green(),
-                item_trimmed
-            );
-        } else {
-            eprintln!(
-                "{} File or directory '{}' not found, skipping.",
-                "Warning:".yellow(),
-                item_str
-            );
         }
     }
 
     Ok(())
 }
 
-pub fn copy_files_from_config(worktree_path: &Path) -> Result<(), String> {
-    let git_root = get_git_root()?;
-    let config_path = git_root.join(".gwtconfig");
-    let files_to_copy = get_files_from_config(&config_path)?;
+/// Links every shared item into a new worktree, unless the checkout already has something there.
+fn link_shared(worktree_path: &Path, store: &Store) -> Result<(), String> {
+    for item in &store.items {
+        let src_path_abs = store.path(item);
+        let dest_path_abs = worktree_path.join(item);
+
+        if src_path_abs.symlink_metadata().is_err() {
+            output::item(
+                Action::Skipped,
+                item,
+                worktree_path,
+                format!("Shared '{item}' is missing from the store, skipping."),
+            );
+            continue;
+        }
+        if dest_path_abs.symlink_metadata().is_ok() {
+            output::item(
+                Action::Skipped,
+                item,
+                worktree_path,
+                format!(
+                    "'{item}' is checked out in the new worktree, not linking the shared copy."
+                ),
+            );
+            continue;
+        }
 
-    if files_to_copy.is_empty() {
-        println!(
-            "{} No .gwtconfig file found or it is empty. No files will be copied.",
-            "Info:".green()
+        create_parent(&dest_path_abs)?;
+        symlink(&src_path_abs, &dest_path_abs).map_err(|e| {
+            format!(
+                "Failed to create symlink from {} to {}: {e}",
+                src_path_abs.display(),
+                dest_path_abs.display()
+            )
+        })?;
+        output::item(
+            Action::Linked,
+            item,
+            worktree_path,
+            format!("Linked shared '{item}' to new worktree."),
         );
+    }
+    Ok(())
+}
+
+/// Removes an existing file, directory or symlink (without following it), if there is one.
+pub fn remove_path(path: &Path) -> Result<(), String> {
+    let Ok(metadata) = path.symlink_metadata() else {
         return Ok(());
+    };
+    if metadata.is_dir() {
+        fs::remove_dir_all(path)
+            .map_err(|e| format!("Failed to remove directory {}: {e}", path.display()))
+    } else {
+        fs::remove_file(path).map_err(|e| format!("Failed to remove file {}: {e}", path.display()))
+    }
+}
+
+/// Points a symlink whose target is `from` or below it at the same place under `to`. Returns
+/// whether `link` was such a symlink.
+pub fn retarget_symlink(link: &Path, from: &Path, to: &Path) -> Result<bool, String> {
+    let Ok(target) = fs::read_link(link) else {
+        return Ok(false);
+    };
+    let Ok(rest) = target.strip_prefix(from) else {
+        return Ok(false);
+    };
+    let new_target = if rest.as_os_str().is_empty() {
+        to.to_path_buf()
+    } else {
+        to.join(rest)
+    };
+    fs::remove_file(link)
+        .map_err(|e| format!("Failed to remove symlink {}: {e}", link.display()))?;
+    symlink(&new_target, link).map_err(|e| {
+        format!(
+            "Failed to create symlink from {} to {}: {e}",
+            new_target.display(),
+            link.display()
+        )
+    })?;
+    Ok(true)
+}
+
+/// Whether two files or directory trees have identical content, following symlinks.
+pub fn same_content(a: &Path, b: &Path) -> bool {
+    let (Ok(meta_a), Ok(meta_b)) = (fs::metadata(a), fs::metadata(b)) else {
+        return false;
+    };
+    if meta_a.is_dir() != meta_b.is_dir() {
+        return false;
+    }
+    if !meta_a.is_dir() {
+        return meta_a.len() == meta_b.len()
+            && matches!((fs::read(a), fs::read(b)), (Ok(x), Ok(y)) if x == y);
+    }
+
+    let entries = |dir: &Path| {
+        let mut entries = WalkDir::new(dir)
+            .min_depth(1)
+            .follow_links(true)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|e| !e.file_type().is_dir())
+            .filter_map(|e| e.path().strip_prefix(dir).ok().map(Path::to_path_buf))
+            .collect::<Vec<_>>();
+        entries.sort();
+        entries
+    };
+    let entries_a = entries(a);
+    entries_a == entries(b)
+        && entries_a
+            .iter()
+            .all(|entry| same_content(&a.join(entry), &b.join(entry)))
+}
+
+/// Copies a file or directory tree, with copy-on-write clones where the file system supports
+/// them. `dest` must not exist, otherwise `cp` would copy a directory into it.
+pub fn cp_cow(src: &Path, dest: &Path) -> Result<(), String> {
+    // Clones on macOS (-c) and GNU (--reflink), plain recursive copy everywhere else.
+    let attempts: [&[&str]; 3] = [&["-Rc"], &["-R", "--reflink=auto"], &["-R"]];
+    let mut stderr = Vec::new();
+    for args in attempts {
+        let output = Command::new("cp")
+            .args(args)
+            .arg(src)
+            .arg(dest)
+            .output()
+            .map_err(|e| format!("Failed to execute cp: {e}"))?;
+        if output.status.success() {
+            return Ok(());
+        }
+        stderr = output.stderr;
+        // Don't let a partial copy make the next attempt copy into it.
+        remove_path(dest)?;
     }
 
-    for item in files_to_copy {
-        let src_path = PathBuf::from(&item);
-        if src_path.exists() {
-            let dest_path = worktree_path.join(&item);
-            if let Some(parent) = dest_path.parent() {
-                fs::create_dir_all(parent)
-                    .map_err(|e| format!("Failed to create directory {}: {e}", parent.display()))?;
+    Err(format!(
+        "Unable to copy {} to {}: {}",
+        src.display(),
+        dest.display(),
+        String::from_utf8_lossy(&stderr).trim()
+    ))
+}
+
+/// Puts a symlink to `src_path` (or, without `link`, a copy of it) at `dest_path`, whatever is
+/// there now. The new item is created under a temporary name next to `dest_path` and renamed
+/// into place, so `dest_path` never is half-written. What it replaces is moved to `backup` if
+/// given, otherwise deleted. Returns whether a symlink was created; if that fails, it copies.
+pub fn install(
+    src_path: &Path,
+    dest_path: &Path,
+    link: bool,
+    backup: Option<&Path>,
+) -> Result<bool, String> {
+    if let Some(parent) = dest_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create directory {}: {e}", parent.display()))?;
+    }
+    let temp = sibling(dest_path, "new");
+    remove_path(&temp)?;
+
+    let linked = link
+        && match symlink(src_path, &temp) {
+            Ok(()) => true,
+            Err(e) => {
+                output::warn(format!(
+                    "Failed to symlink {} ({e}). Falling back to copy.",
+                    dest_path.display()
+                ));
+                false
+            }
+        };
+    let installed = if linked {
+        Ok(())
+    } else {
+        cp_cow(src_path, &temp)
+    }
+    .and_then(|()| swap(&temp, dest_path, backup));
+    if installed.is_err() {
+        let _ = remove_path(&temp);
+    }
+    installed.map(|()| linked)
+}
+
+/// Renames `temp` to `dest`, moving what was at `dest` to `backup` (or deleting it).
+fn swap(temp: &Path, dest: &Path, backup: Option<&Path>) -> Result<(), String> {
+    let rename = |from: &Path, to: &Path| {
+        fs::rename(from, to).map_err(|e| {
+            format!(
+                "Failed to rename {} to {}: {e}",
+                from.display(),
+                to.display()
+            )
+        })
+    };
+
+    let temp_is_dir = temp.symlink_metadata().is_ok_and(|m| m.is_dir());
+    match dest.symlink_metadata() {
+        // rename() only replaces a directory with an empty one, and a file with a file, so
+        // whatever is in the way is moved out of it first.
+        Ok(metadata) if metadata.is_dir() || temp_is_dir => {
+            if let Some(backup) = backup {
+                refuse_existing(backup)?;
+            }
+            let old = sibling(dest, "old");
+            remove_path(&old)?;
+            rename(dest, &old)?;
+            if let Err(e) = rename(temp, dest) {
+                let _ = fs::rename(&old, dest);
+                return Err(e);
+            }
+            match backup {
+                Some(backup) => move_path(&old, backup).inspect_err(|_| {
+                    // Put the original back rather than leave it under the temporary name.
+                    if fs::rename(dest, temp).is_ok() {
+                        let _ = fs::rename(&old, dest);
+                    }
+                }),
+                None => remove_path(&old),
             }
-            cp_cow(&src_path, &dest_path)?;
-            println!("{} Copied '{}' to new worktree.", "Info:".green(), item);
-        } else {
-            eprintln!(
-                "{} File or directory '{}' not found, skipping.",
-                "Warning:".yellow(),
-                item
-            );
         }
+        Ok(_) => {
+            if let Some(backup) = backup {
+                preserve(dest, backup)?;
+            }
+            rename(temp, dest)
+        }
+        Err(_) => rename(temp, dest),
     }
+}
 
-    Ok(())
+/// `.name.gwt-<tag>-<pid>` in the directory of `path`.
+fn sibling(path: &Path, tag: &str) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{name}.gwt-{tag}-{}", std::process::id()))
 }
 
-pub fn cp_cow(src: &Path, dest: &Path) -> Result<(), String> {
-    // Try cp with copy-on-write (macOS)
-    let mut cmd = Command::new("cp");
-    cmd.arg("-Rc").arg(src).arg(dest);
-    if cmd.status().is_ok_and(|s| s.success()) {
-        return Ok(());
+fn create_parent(path: &Path) -> Result<(), String> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create directory {}: {e}", parent.display())),
+        None => Ok(()),
     }
+}
 
-    // Try cp with copy-on-write (GNU)
-    let mut cmd = Command::new("cp");
-    cmd.arg("-R").arg("--reflink=auto").arg(src).arg(dest);
-    if cmd.status().is_ok_and(|s| s.success()) {
+/// Moves a file or directory, copying it if it can't be renamed (e.g. across file systems).
+/// Refuses to replace anything at `to`.
+pub fn move_path(from: &Path, to: &Path) -> Result<(), String> {
+    refuse_existing(to)?;
+    create_parent(to)?;
+    if fs::rename(from, to).is_ok() {
         return Ok(());
     }
+    cp_cow(from, to)?;
+    remove_path(from)
+}
 
-    // Fallback to standard recursive copy
-    let mut cmd = Command::new("cp");
-    cmd.arg("-R").arg(src).arg(dest);
-    if cmd.status().is_ok_and(|s| s.success()) {
+/// Keeps the current content of the file `path` at `to`; a hard link if possible. Refuses to
+/// replace anything at `to`.
+fn preserve(path: &Path, to: &Path) -> Result<(), String> {
+    refuse_existing(to)?;
+    create_parent(to)?;
+    if fs::hard_link(path, to).is_ok() {
         return Ok(());
     }
+    fs::copy(path, to)
+        .map(|_| ())
+        .map_err(|e| format!("Failed to back up {}: {e}", path.display()))
+}
 
-    Err(format!(
-        "{} Unable to copy {} to {}",
-        "Warning:".yellow(),
-        src.display(),
-        dest.display()
-    ))
+fn refuse_existing(path: &Path) -> Result<(), String> {
+    match path.symlink_metadata() {
+        Ok(_) => Err(format!("{} already exists", path.display())),
+        Err(_) => Ok(()),
+    }
 }
@@ -1,8 +1,15 @@
 use colored::*;
-use std::path::PathBuf;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-pub fn get_git_root() -> Result<PathBuf, String> {
+use serde::Serialize;
+
+use crate::error::{Error, ErrorCode};
+use crate::output;
+
+pub fn get_git_root() -> Result<PathBuf, Error> {
     let output = Command::new("git")
         .arg("rev-parse")
         .arg("--show-toplevel")
@@ -10,7 +17,10 @@ pub fn get_git_root() -> Result<PathBuf, String> {
         .map_err(|e| format!("Failed to execute git command: {e}"))?;
 
     if !output.status.success() {
-        return Err("Not in a git repository".to_string());
+        return Err(Error::new(
+            ErrorCode::NotARepository,
+            "Not in a git repository",
+        ));
     }
 
     Ok(PathBuf::from(
@@ -18,6 +28,37 @@ pub fn get_git_root() -> Result<PathBuf, String> {
     ))
 }
 
+pub fn get_git_common_dir() -> Result<PathBuf, Error> {
+    let output = Command::new("git")
+        .arg("rev-parse")
+        .arg("--git-common-dir")
+        .output()
+        .map_err(|e| format!("Failed to execute git command: {e}"))?;
+
+    if !output.status.success() {
+        return Err(Error::new(
+            ErrorCode::NotARepository,
+            "Not in a git repository",
+        ));
+    }
+
+    let common_dir = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    if common_dir.is_absolute() {
+        return Ok(common_dir);
+    }
+    let current_dir =
+        std::env::current_dir().map_err(|e| format!("couldn't get current directory: {e}"))?;
+    Ok(current_dir.join(common_dir))
+}
+
+/// Directory inside the git common dir where gwt keeps its state; shared by all worktrees.
+pub fn get_gwt_dir() -> Result<PathBuf, Error> {
+    let gwt_dir = get_git_common_dir()?.join("gwt");
+    fs::create_dir_all(&gwt_dir)
+        .map_err(|e| format!("Failed to create directory {}: {e}", gwt_dir.display()))?;
+    Ok(gwt_dir)
+}
+
 pub fn pull_latest() -> Result<(), String> {
     let output = Command::new("git")
         .arg("pull")
@@ -27,58 +68,215 @@ pub fn pull_latest() -> Result<(), String> {
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
         if !stderr.contains("There is no tracking information for the current branch.") {
-            eprintln!(
-                "{} Unable to run git pull, there may not be an upstream",
-                "Warning:".yellow()
-            );
+            output::warn("Unable to run git pull, there may not be an upstream");
         }
     }
     Ok(())
 }
 
-pub fn create_worktree(branch_name: &str, dirname: &str) -> Result<(), String> {
-    let worktree_path = format!("../{dirname}");
-    let local_branch_exists_output = Command::new("git")
-        .arg("for-each-ref")
-        .arg("--format=%(refname:lstrip=2)")
-        .arg("refs/heads")
-        .output()
-        .map_err(|e| format!("Failed to check for local branches: {e}"))?;
-    let local_branch_exists = local_branch_exists_output.stdout;
+fn ref_exists(refname: &str) -> Result<bool, String> {
+    let status = Command::new("git")
+        .arg("show-ref")
+        .arg("--verify")
+        .arg("--quiet")
+        .arg(refname)
+        .status()
+        .map_err(|e| format!("Failed to execute git show-ref: {e}"))?;
+    Ok(status.success())
+}
 
-    let remote_branch_exists_output = Command::new("git")
-        .arg("for-each-ref")
-        .arg("--format=%(refname:lstrip=3)")
-        .arg("refs/remotes/origin")
-        .output()
-        .map_err(|e| format!("Failed to check for remote branches: {e}"))?;
-    let remote_branch_exists = remote_branch_exists_output.stdout;
+pub fn local_branch_exists(branch_name: &str) -> Result<bool, String> {
+    ref_exists(&format!("refs/heads/{branch_name}"))
+}
+
+/// The configured remotes that have a branch named exactly `branch_name`.
+pub fn remotes_with_branch(branch_name: &str) -> Result<Vec<String>, String> {
+    let mut remotes = Vec::new();
+    for remote in get_remotes()? {
+        if ref_exists(&format!("refs/remotes/{remote}/{branch_name}"))? {
+            remotes.push(remote);
+        }
+    }
+    Ok(remotes)
+}
+
+/// What `git worktree add` checks out.
+pub enum Checkout<'a> {
+    /// The existing local branch
+    Existing,
+    /// A new branch starting at the given commit
+    New(&'a str),
+    /// A new branch tracking the given remote branch, e.g. `origin/feature`
+    Track(&'a str),
+    /// The given commit with a detached HEAD
+    Detach(&'a str),
+}
 
+pub fn create_worktree(
+    branch_name: &str,
+    worktree_path: &Path,
+    checkout: Checkout,
+) -> Result<(), Error> {
     let mut cmd = Command::new("git");
     cmd.arg("worktree").arg("add");
 
-    if String::from_utf8_lossy(&local_branch_exists).contains(branch_name)
-        || String::from_utf8_lossy(&remote_branch_exists).contains(branch_name)
-    {
-        cmd.arg(&worktree_path).arg(branch_name);
-    } else {
-        cmd.arg("-b").arg(branch_name).arg(&worktree_path);
+    match checkout {
+        Checkout::Existing => {
+            cmd.arg(worktree_path).arg(branch_name);
+        }
+        Checkout::New(start_point) => {
+            cmd.arg("-b")
+                .arg(branch_name)
+                .arg(worktree_path)
+                .arg(start_point);
+        }
+        Checkout::Track(remote_branch) => {
+            cmd.arg("--track")
+                .arg("-b")
+                .arg(branch_name)
+                .arg(worktree_path)
+                .arg(remote_branch);
+        }
+        Checkout::Detach(commit) => {
+            cmd.arg("--detach").arg(worktree_path).arg(commit);
+        }
     }
 
     let status = cmd
+        .stdout(output::child_stdout())
         .status()
         .map_err(|e| format!("Failed to create git worktree: {e}"))?;
 
     if !status.success() {
-        return Err(format!(
-            "Failed to create git worktree for branch '{branch_name}'"
+        return Err(Error::new(
+            ErrorCode::GitFailed,
+            format!("Failed to create git worktree for branch '{branch_name}'"),
+        ));
+    }
+
+    Ok(())
+}
+
+/// The full hash of the commit `rev` points to, `None` if it doesn't resolve.
+pub fn resolve_commit(rev: &str) -> Result<Option<String>, String> {
+    let output = Command::new("git")
+        .arg("rev-parse")
+        .arg("--verify")
+        .arg("--quiet")
+        .arg(format!("{rev}^{{commit}}"))
+        .output()
+        .map_err(|e| format!("Failed to execute git rev-parse: {e}"))?;
+
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(
+        String::from_utf8_lossy(&output.stdout).trim().to_string(),
+    ))
+}
+
+/// One line describing a commit: abbreviated hash and subject.
+pub fn commit_summary(rev: &str) -> Result<String, String> {
+    let output = Command::new("git")
+        .arg("log")
+        .arg("-1")
+        .arg("--format=%h %s")
+        .arg(rev)
+        .output()
+        .map_err(|e| format!("Failed to execute git log: {e}"))?;
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+pub fn get_remotes() -> Result<Vec<String>, String> {
+    let output = Command::new("git")
+        .arg("remote")
+        .output()
+        .map_err(|e| format!("Failed to execute git remote: {e}"))?;
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+/// Fetches `src` from `remote` into the local ref `dst`, overwriting it. Returns `false` if the
+/// remote doesn't have `src`.
+pub fn fetch_ref(remote: &str, src: &str, dst: &str) -> Result<bool, Error> {
+    let output = Command::new("git")
+        .arg("fetch")
+        .arg("--quiet")
+        .arg("--no-tags")
+        .arg(remote)
+        .arg(format!("+{src}:{dst}"))
+        .output()
+        .map_err(|e| format!("Failed to execute git fetch: {e}"))?;
+
+    if output.status.success() {
+        return Ok(true);
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("couldn't find remote ref") {
+        return Ok(false);
+    }
+    Err(Error::new(
+        ErrorCode::GitFailed,
+        format!("Failed to fetch {src} from {remote}: {}", stderr.trim()),
+    ))
+}
+
+pub fn delete_ref(refname: &str) -> Result<(), Error> {
+    let status = Command::new("git")
+        .arg("update-ref")
+        .arg("-d")
+        .arg(refname)
+        .status()
+        .map_err(|e| format!("Failed to execute git update-ref: {e}"))?;
+
+    if !status.success() {
+        return Err(Error::new(
+            ErrorCode::GitFailed,
+            format!("Failed to delete {refname}"),
         ));
     }
+    Ok(())
+}
+
+pub fn fetch(remote: &str) -> Result<(), Error> {
+    let status = Command::new("git")
+        .arg("fetch")
+        .arg(remote)
+        .stdout(output::child_stdout())
+        .status()
+        .map_err(|e| format!("Failed to execute git fetch: {e}"))?;
 
+    if !status.success() {
+        return Err(Error::new(
+            ErrorCode::GitFailed,
+            format!("Failed to fetch from {remote}"),
+        ));
+    }
     Ok(())
 }
 
-pub fn get_worktrees() -> Result<Vec<PathBuf>, String> {
+/// A single record of `git worktree list --porcelain`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Worktree {
+    pub path: PathBuf,
+    pub head: Option<String>,
+    /// Short branch name (without `refs/heads/`), `None` for bare or detached worktrees
+    pub branch: Option<String>,
+    pub bare: bool,
+    pub detached: bool,
+    /// Lock reason if the worktree is locked (may be empty)
+    pub locked: Option<String>,
+    /// Prune reason if git considers the worktree prunable (may be empty)
+    pub prunable: Option<String>,
+    /// Whether this is the main worktree (always the first entry git reports)
+    pub main: bool,
+}
+
+pub fn get_worktrees() -> Result<Vec<Worktree>, String> {
     let output = Command::new("git")
         .arg("worktree")
         .arg("list")
@@ -90,30 +288,149 @@ pub fn get_worktrees() -> Result<Vec<PathBuf>, String> {
         return Err("Failed to list git worktrees".to_string());
     }
 
-    let mut worktrees = Vec::new();
-    let output_str = String::from_utf8_lossy(&output.stdout);
-    for line in output_str.lines() {
-        if line.starts_with("worktree ") {
-            worktrees.push(PathBuf::from(line.split_at(9).1));
+    Ok(parse_worktree_porcelain(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+fn parse_worktree_porcelain(porcelain: &str) -> Vec<Worktree> {
+    let mut worktrees: Vec<Worktree> = Vec::new();
+    for line in porcelain.lines() {
+        let (key, value) = match line.split_once(' ') {
+            Some((key, value)) => (key, Some(value)),
+            None => (line, None),
+        };
+
+        if key == "worktree" {
+            worktrees.push(Worktree {
+                path: PathBuf::from(value.unwrap_or_default()),
+                main: worktrees.is_empty(),
+                ..Default::default()
+            });
+            continue;
+        }
+
+        let Some(worktree) = worktrees.last_mut() else {
+            continue;
+        };
+        match key {
+            "HEAD" => worktree.head = value.map(str::to_string),
+            "branch" => {
+                worktree.branch = value.map(|b| b.trim_start_matches("refs/heads/").to_string())
+            }
+            "bare" => worktree.bare = true,
+            "detached" => worktree.detached = true,
+            "locked" => worktree.locked = Some(value.unwrap_or_default().to_string()),
+            "prunable" => worktree.prunable = Some(value.unwrap_or_default().to_string()),
+            _ => {}
         }
     }
-    Ok(worktrees)
+    worktrees
 }
 
-pub fn get_default_branch() -> Result<String, String> {
-    // Try to get the HEAD branch from `git remote show origin`
+/// The main worktree (or the bare repository) every other worktree belongs to.
+pub fn get_repo_root() -> Result<PathBuf, Error> {
+    get_worktrees()?
+        .into_iter()
+        .next()
+        .map(|w| w.path)
+        .ok_or_else(|| Error::new(ErrorCode::NotARepository, "Not in a git repository"))
+}
+
+/// Returns `(ahead, behind)` of `branch` relative to its upstream, or `None` if it has no upstream.
+pub fn ahead_behind(branch: &str) -> Result<Option<(usize, usize)>, String> {
+    let output = Command::new("git")
+        .arg("rev-list")
+        .arg("--left-right")
+        .arg("--count")
+        .arg(format!("{branch}...{branch}@{{upstream}}"))
+        .output()
+        .map_err(|e| format!("Failed to execute git rev-list: {e}"))?;
+
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut counts = stdout.split_whitespace().map(|n| n.parse::<usize>());
+    match (counts.next(), counts.next()) {
+        (Some(Ok(ahead)), Some(Ok(behind))) => Ok(Some((ahead, behind))),
+        _ => Ok(None),
+    }
+}
+
+pub fn worktree_has_changes(worktree_path: &Path) -> Result<bool, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(worktree_path)
+        .arg("status")
+        .arg("--porcelain")
+        .output()
+        .map_err(|e| format!("Failed to execute git status --porcelain: {e}"))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to get status of worktree {}",
+            worktree_path.display()
+        ));
+    }
+
+    Ok(!output.stdout.is_empty())
+}
+
+/// Returns the subject and relative age of the commit checked out in `worktree_path`.
+pub fn last_commit(worktree_path: &Path) -> Result<Option<(String, String)>, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(worktree_path)
+        .arg("log")
+        .arg("-1")
+        .arg("--format=%s%x1f%cr")
+        .output()
+        .map_err(|e| format!("Failed to execute git log: {e}"))?;
+
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout
+        .trim_end()
+        .split_once('\x1f')
+        .map(|(subject, age)| (subject.to_string(), age.to_string())))
+}
+
+pub fn get_default_branch(remote: &str) -> Result<String, String> {
+    // Use the remote HEAD recorded locally by clone or `git remote set-head`
+    let output = Command::new("git")
+        .arg("symbolic-ref")
+        .arg("--quiet")
+        .arg(format!("refs/remotes/{remote}/HEAD"))
+        .output()
+        .map_err(|e| format!("Failed to execute git symbolic-ref: {e}"))?;
+    if output.status.success() {
+        let head = String::from_utf8_lossy(&output.stdout);
+        if let Some(branch) = head.trim().strip_prefix(&format!("refs/remotes/{remote}/")) {
+            return Ok(branch.to_string());
+        }
+    }
+
+    // Try to get the HEAD branch from `git remote show <remote>`
     let output = Command::new("git")
         .arg("remote")
         .arg("show")
-        .arg("origin")
+        .arg(remote)
         .output()
-        .map_err(|e| format!("Failed to execute git remote show origin: {e}"))?;
+        .map_err(|e| format!("Failed to execute git remote show {remote}: {e}"))?;
 
     if output.status.success() {
         let stdout = String::from_utf8_lossy(&output.stdout);
         for line in stdout.lines() {
             if line.trim().starts_with("HEAD branch:") {
-                return Ok(line.split(':').nth(1).unwrap().trim().to_string());
+                let branch = line.split(':').nth(1).unwrap().trim();
+                if branch != "(unknown)" {
+                    return Ok(branch.to_string());
+                }
             }
         }
     }
@@ -123,7 +440,7 @@ pub fn get_default_branch() -> Result<String, String> {
     for branch in common_branches {
         let output = Command::new("git")
             .arg("show-ref")
-            .arg(format!("refs/remotes/origin/{branch}"))
+            .arg(format!("refs/remotes/{remote}/{branch}"))
             .output()
             .map_err(|e| format!("Failed to execute git show-ref: {e}"))?;
         if output.status.success() {
@@ -134,33 +451,366 @@ pub fn get_default_branch() -> Result<String, String> {
     Err("Could not determine default branch.".to_string())
 }
 
-pub fn branch_has_changes() -> Result<bool, String> {
+/// `git status --porcelain` lines of the worktree: modified, staged and untracked files.
+pub fn changed_files(worktree_path: &Path) -> Result<Vec<String>, String> {
     let output = Command::new("git")
+        .arg("-C")
+        .arg(worktree_path)
         .arg("status")
-        .arg("-s")
+        .arg("--porcelain")
         .output()
-        .map_err(|e| format!("Failed to execute git status -s: {e}"))?;
+        .map_err(|e| format!("Failed to execute git status --porcelain: {e}"))?;
 
-    let has_changes = !output.stdout.is_empty();
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to get status of worktree {}",
+            worktree_path.display()
+        ));
+    }
 
-    Ok(has_changes)
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect())
 }
 
-pub fn remove_worktree(dirname: &str) -> Result<(), String> {
-    Command::new("git")
-        .arg("worktree")
-        .arg("remove")
-        .arg(dirname)
+/// Whether git tracks `path` (a file, or any file below a directory) in the worktree.
+pub fn is_tracked(worktree_path: &Path, path: &str) -> Result<bool, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(worktree_path)
+        .arg("ls-files")
+        .arg("--")
+        .arg(path)
+        .output()
+        .map_err(|e| format!("Failed to execute git ls-files: {e}"))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to list the files of {}",
+            worktree_path.display()
+        ));
+    }
+    Ok(!output.stdout.is_empty())
+}
+
+/// Whether `path` is ignored by a `.gitignore`, `info/exclude` or the global excludes file.
+pub fn is_ignored(worktree_path: &Path, path: &str) -> Result<bool, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(worktree_path)
+        .arg("check-ignore")
+        .arg("--quiet")
+        .arg("--no-index")
+        .arg("--")
+        .arg(path)
+        .output()
+        .map_err(|e| format!("Failed to execute git check-ignore: {e}"))?;
+
+    // Exits with 1 if the path isn't ignored.
+    match output.status.code() {
+        Some(0) => Ok(true),
+        Some(1) => Ok(false),
+        _ => Err(format!("Failed to check whether {path} is ignored")),
+    }
+}
+
+/// Appends `pattern` to `info/exclude`, which applies to every worktree, unless it's already
+/// there.
+pub fn exclude(pattern: &str) -> Result<(), Error> {
+    let exclude_path = get_git_common_dir()?.join("info").join("exclude");
+    let existing = fs::read_to_string(&exclude_path).unwrap_or_default();
+    if existing.lines().any(|line| line.trim() == pattern) {
+        return Ok(());
+    }
+
+    if let Some(parent) = exclude_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create directory {}: {e}", parent.display()))?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&exclude_path)
+        .map_err(|e| format!("Failed to open {}: {e}", exclude_path.display()))?;
+    let separator = if existing.is_empty() || existing.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+    writeln!(file, "{separator}{pattern}")
+        .map_err(|e| format!("Failed to write {}: {e}", exclude_path.display()))?;
+    Ok(())
+}
+
+/// The upstream of `branch_name`, e.g. `origin/feature`, if one is configured.
+pub fn upstream(branch_name: &str) -> Result<Option<String>, String> {
+    let output = Command::new("git")
+        .arg("rev-parse")
+        .arg("--abbrev-ref")
+        .arg("--symbolic-full-name")
+        .arg(format!("{branch_name}@{{upstream}}"))
+        .output()
+        .map_err(|e| format!("Failed to execute git rev-parse: {e}"))?;
+
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(
+        String::from_utf8_lossy(&output.stdout).trim().to_string(),
+    ))
+}
+
+/// Number of commits matched by the `git rev-list` arguments, run in `worktree_path`.
+pub fn count_commits(worktree_path: &Path, args: &[&str]) -> Result<usize, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(worktree_path)
+        .arg("rev-list")
+        .arg("--count")
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to execute git rev-list: {e}"))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to count commits: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .map_err(|e| format!("Failed to parse git rev-list output: {e}"))
+}
+
+/// Whether `commit` is an ancestor of (merged into) `target`.
+pub fn is_ancestor(commit: &str, target: &str) -> Result<bool, String> {
+    let output = Command::new("git")
+        .arg("merge-base")
+        .arg("--is-ancestor")
+        .arg(commit)
+        .arg(target)
+        .output()
+        .map_err(|e| format!("Failed to execute git merge-base: {e}"))?;
+
+    match output.status.code() {
+        Some(0) => Ok(true),
+        Some(1) => Ok(false),
+        _ => Err(format!(
+            "Failed to check whether {commit} is merged into {target}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    }
+}
+
+/// Whether `branch_name` has an upstream that no longer exists on the remote.
+pub fn upstream_gone(branch_name: &str) -> Result<bool, String> {
+    let output = Command::new("git")
+        .arg("for-each-ref")
+        .arg("--format=%(upstream:track)")
+        .arg(format!("refs/heads/{branch_name}"))
+        .output()
+        .map_err(|e| format!("Failed to execute git for-each-ref: {e}"))?;
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim() == "[gone]")
+}
+
+/// When the worktree was last used, in seconds since the Unix epoch: the later of its last
+/// commit and the last update of its HEAD reflog (commits, checkouts, resets).
+pub fn last_activity(worktree_path: &Path) -> Result<Option<u64>, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(worktree_path)
+        .arg("log")
+        .arg("-1")
+        .arg("--format=%ct")
+        .output()
+        .map_err(|e| format!("Failed to execute git log: {e}"))?;
+    let commit_time = String::from_utf8_lossy(&output.stdout).trim().parse().ok();
+
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(worktree_path)
+        .arg("rev-parse")
+        .arg("--absolute-git-dir")
+        .output()
+        .map_err(|e| format!("Failed to execute git rev-parse: {e}"))?;
+    let git_dir = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let reflog_time = fs::metadata(git_dir.join("logs").join("HEAD"))
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs());
+
+    Ok(commit_time.max(reflog_time))
+}
+
+/// Entries of `git stash list` (like `stash@{0}: WIP on main: 1a2b3c4 subject`) that were
+/// created on `branch_name`.
+pub fn stashes_on_branch(branch_name: &str) -> Result<Vec<String>, String> {
+    let output = Command::new("git")
+        .arg("stash")
+        .arg("list")
+        .arg("--format=%gd: %gs")
+        .output()
+        .map_err(|e| format!("Failed to execute git stash list: {e}"))?;
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| {
+            let subject = line.split_once(": ").map_or("", |(_, subject)| subject);
+            subject.starts_with(&format!("WIP on {branch_name}:"))
+                || subject.starts_with(&format!("On {branch_name}:"))
+        })
+        .map(str::to_string)
+        .collect())
+}
+
+/// Runs `git worktree remove`; with `force`, changes are discarded and locks ignored. Fails with
+/// git's stderr if git refuses or the directory is still there afterwards.
+pub fn remove_worktree(worktree_path: &Path, force: bool) -> Result<(), Error> {
+    let mut cmd = Command::new("git");
+    cmd.arg("worktree").arg("remove");
+    if force {
+        // Given twice, --force also removes locked worktrees.
+        cmd.arg("--force").arg("--force");
+    }
+    let output = cmd
+        .arg(worktree_path)
         .output()
         .map_err(|e| format!("Failed to execute git worktree remove: {e}"))?;
 
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let code = if stderr.contains("locked working tree") {
+            ErrorCode::Locked
+        } else if stderr.contains("modified or untracked files") {
+            ErrorCode::WorktreeDirty
+        } else {
+            ErrorCode::GitFailed
+        };
+        return Err(Error::new(
+            code,
+            format!("git worktree remove failed for {}", worktree_path.display()),
+        )
+        .with_stderr(&output.stderr));
+    }
+
+    if worktree_path.symlink_metadata().is_ok() {
+        return Err(Error::new(
+            ErrorCode::Io,
+            format!(
+                "{} still exists after git worktree remove",
+                worktree_path.display()
+            ),
+        )
+        .with_stderr(&output.stderr));
+    }
     Ok(())
 }
 
-pub fn delete_branch(branch_name: &str) -> Result<(), String> {
+/// Renames a local branch with `git branch -m`; its config (e.g. the upstream) moves along.
+pub fn rename_branch(old_name: &str, new_name: &str) -> Result<(), Error> {
     let output = Command::new("git")
         .arg("branch")
-        .arg("-d")
+        .arg("-m")
+        .arg(old_name)
+        .arg(new_name)
+        .output()
+        .map_err(|e| format!("Failed to execute git branch -m: {e}"))?;
+
+    if !output.status.success() {
+        return Err(Error::new(
+            ErrorCode::GitFailed,
+            format!(
+                "Could not rename branch {} to {}",
+                old_name.green(),
+                new_name.green()
+            ),
+        )
+        .with_stderr(&output.stderr));
+    }
+    Ok(())
+}
+
+/// Moves a worktree with `git worktree move`. `destination` must not exist, otherwise git moves
+/// the worktree into it.
+pub fn move_worktree(worktree_path: &Path, destination: &Path) -> Result<(), Error> {
+    let output = Command::new("git")
+        .arg("worktree")
+        .arg("move")
+        .arg(worktree_path)
+        .arg(destination)
+        .output()
+        .map_err(|e| format!("Failed to execute git worktree move: {e}"))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let code = if stderr.contains("locked working tree") {
+            ErrorCode::Locked
+        } else {
+            ErrorCode::GitFailed
+        };
+        return Err(Error::new(
+            code,
+            format!("git worktree move failed for {}", worktree_path.display()),
+        )
+        .with_stderr(&output.stderr));
+    }
+    Ok(())
+}
+
+/// The remote and remote branch `branch_name` tracks, read from its config (the remote branch
+/// doesn't have to exist).
+pub fn tracking(branch_name: &str) -> Result<Option<(String, String)>, String> {
+    let get = |key: &str| -> Result<Option<String>, String> {
+        let output = Command::new("git")
+            .arg("config")
+            .arg("--get")
+            .arg(format!("branch.{branch_name}.{key}"))
+            .output()
+            .map_err(|e| format!("Failed to execute git config: {e}"))?;
+        Ok(output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string()))
+    };
+    let (Some(remote), Some(merge)) = (get("remote")?, get("merge")?) else {
+        return Ok(None);
+    };
+    let remote_branch = merge.strip_prefix("refs/heads/").unwrap_or(&merge);
+    Ok(Some((remote, remote_branch.to_string())))
+}
+
+/// Makes `branch_name` track `remote_branch` on `remote`, which doesn't have to exist yet.
+pub fn set_tracking(branch_name: &str, remote: &str, remote_branch: &str) -> Result<(), Error> {
+    for (key, value) in [
+        ("remote", remote.to_string()),
+        ("merge", format!("refs/heads/{remote_branch}")),
+    ] {
+        let output = Command::new("git")
+            .arg("config")
+            .arg(format!("branch.{branch_name}.{key}"))
+            .arg(value)
+            .output()
+            .map_err(|e| format!("Failed to execute git config: {e}"))?;
+        if !output.status.success() {
+            return Err(Error::new(
+                ErrorCode::GitFailed,
+                format!("Could not set the upstream of {}", branch_name.green()),
+            )
+            .with_stderr(&output.stderr));
+        }
+    }
+    Ok(())
+}
+
+/// Deletes a local branch; unless `force` is set, only if it is fully merged.
+pub fn delete_branch(branch_name: &str, force: bool) -> Result<(), Error> {
+    let output = Command::new("git")
+        .arg("branch")
+        .arg(if force { "-D" } else { "-d" })
         .arg(branch_name)
         .output()
         .map_err(|e| format!("Failed to execute git branch -d: {e}"))?;
@@ -168,17 +818,96 @@ pub fn delete_branch(branch_name: &str) -> Result<(), String> {
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
         if stderr.contains("is not fully merged") {
-            return Err(format!(
-                "The branch {} is not fully merged and will not be deleted",
-                branch_name.green(),
+            return Err(Error::new(
+                ErrorCode::BranchNotMerged,
+                format!(
+                    "The branch {} is not fully merged and will not be deleted",
+                    branch_name.green(),
+                ),
             ));
         }
-        return Err(format!(
-            "Could not delete branch {}: {}",
-            branch_name.green(),
-            stderr,
-        ));
+        return Err(Error::new(
+            ErrorCode::GitFailed,
+            format!("Could not delete branch {}", branch_name.green()),
+        )
+        .with_stderr(&output.stderr));
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_worktree_porcelain() {
+        let porcelain = "\
+worktree /repo/.bare
+bare
+
+worktree /repo/main
+HEAD 1111111111111111111111111111111111111111
+branch refs/heads/main
+
+worktree /repo/v1.2.0
+HEAD 2222222222222222222222222222222222222222
+detached
+
+worktree /repo/feature
+HEAD 3333333333333333333333333333333333333333
+branch refs/heads/feature/login
+locked on a usb stick
+
+worktree /repo/experiment
+HEAD 4444444444444444444444444444444444444444
+branch refs/heads/experiment
+locked
+
+worktree /repo/gone
+HEAD 5555555555555555555555555555555555555555
+branch refs/heads/gone
+prunable gitdir file points to non-existent location
+";
+        let worktrees = parse_worktree_porcelain(porcelain);
+        assert_eq!(worktrees.len(), 6);
+        let [bare, main, detached, feature, experiment, gone] = &worktrees[..] else {
+            unreachable!();
+        };
+
+        assert_eq!(bare.path, PathBuf::from("/repo/.bare"));
+        assert!(bare.bare);
+        assert_eq!(bare.head, None);
+        assert_eq!(bare.branch, None);
+
+        assert_eq!(main.branch.as_deref(), Some("main"));
+        assert_eq!(
+            main.head.as_deref(),
+            Some("1111111111111111111111111111111111111111")
+        );
+        assert!(!main.bare && !main.detached);
+
+        assert!(detached.detached);
+        assert_eq!(detached.branch, None);
+
+        assert_eq!(feature.branch.as_deref(), Some("feature/login"));
+        assert_eq!(feature.locked.as_deref(), Some("on a usb stick"));
+
+        assert_eq!(experiment.locked.as_deref(), Some(""));
+        assert_eq!(experiment.prunable, None);
+
+        assert_eq!(
+            gone.prunable.as_deref(),
+            Some("gitdir file points to non-existent location")
+        );
+        assert_eq!(gone.locked, None);
+
+        assert!(worktrees[0].main);
+        assert!(worktrees[1..].iter().all(|w| !w.main));
+    }
+
+    #[test]
+    fn parses_empty_porcelain() {
+        assert!(parse_worktree_porcelain("").is_empty());
+    }
+}
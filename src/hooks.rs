@@ -0,0 +1,58 @@
+use std::path::Path;
+use std::process::Command;
+
+use crate::config::{Config, Hook};
+use crate::error::{Error, ErrorCode};
+use crate::output;
+
+/// What a hook is run for; exposed to hook commands as `GWT_*` environment variables.
+pub struct HookContext<'a> {
+    pub branch: Option<&'a str>,
+    pub worktree: &'a Path,
+    pub source_worktree: Option<&'a Path>,
+    pub repo_root: &'a Path,
+}
+
+/// Runs the commands configured for `hook` through `sh -c`, in `cwd`. Stops at the first command
+/// that fails; for `pre-` hooks the caller must then abort the operation.
+pub fn run_hook(
+    config: &Config,
+    hook: Hook,
+    cwd: &Path,
+    context: &HookContext,
+) -> Result<(), Error> {
+    let Some(commands) = config.hooks.get(&hook) else {
+        return Ok(());
+    };
+
+    for command in commands {
+        output::info(format!("Running {} hook: {command}", hook.name()));
+
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg(command)
+            .current_dir(cwd)
+            .stdout(output::child_stdout())
+            .env("GWT_HOOK", hook.name())
+            .env("GWT_WORKTREE", context.worktree)
+            .env("GWT_REPO_ROOT", context.repo_root);
+        if let Some(branch) = context.branch {
+            cmd.env("GWT_BRANCH", branch);
+        }
+        if let Some(source_worktree) = context.source_worktree {
+            cmd.env("GWT_SOURCE_WORKTREE", source_worktree);
+        }
+
+        let status = cmd
+            .status()
+            .map_err(|e| format!("Failed to run {} hook '{command}': {e}", hook.name()))?;
+        if !status.success() {
+            return Err(Error::new(
+                ErrorCode::HookFailed,
+                format!("The {} hook '{command}' failed ({status})", hook.name()),
+            ));
+        }
+    }
+
+    Ok(())
+}
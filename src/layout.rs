@@ -0,0 +1,209 @@
+use std::env;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::Config;
+use crate::error::{Error, ErrorCode};
+use crate::git_utils::{exclude, get_repo_root};
+
+/// Directory name for a branch: `feature/login` becomes `feature_login`. Underscores and percent
+/// signs are escaped (`%5F`, `%25`), so the mapping is reversible and `feature_login` gets a
+/// different directory.
+pub fn branch_slug(branch_name: &str) -> String {
+    branch_name
+        .replace('%', "%25")
+        .replace('_', "%5F")
+        .replace('/', "_")
+}
+
+/// Where the worktree for `branch_name` lives according to the `[layout]` template.
+/// Relative templates are resolved against the main worktree (or the bare repository).
+pub fn worktree_path(config: &Config, branch_name: &str) -> Result<PathBuf, Error> {
+    let repo_root = get_repo_root()?;
+    let expanded = expand_template(&config.layout.path, &repo_name(&repo_root), branch_name)?;
+
+    let path = match expanded.strip_prefix("~/") {
+        Some(rest) => home_dir()?.join(rest),
+        None => repo_root.join(&expanded),
+    };
+    Ok(normalize(&path))
+}
+
+fn expand_template(template: &str, repo: &str, branch_name: &str) -> Result<String, Error> {
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            return Err(invalid_template(template, "unclosed '{'"));
+        };
+        let value = match &rest[start + 1..start + end] {
+            "repo" => repo.to_string(),
+            "branch" => branch_name.to_string(),
+            "branch_slug" => branch_slug(branch_name),
+            variable => {
+                return Err(invalid_template(
+                    template,
+                    &format!("unknown variable '{{{variable}}}'"),
+                ))
+            }
+        };
+        expanded.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+fn invalid_template(template: &str, reason: &str) -> Error {
+    Error::new(
+        ErrorCode::Config,
+        format!("Invalid layout.path '{template}': {reason}"),
+    )
+}
+
+/// The repository name: the main worktree's directory, or for bare repositories the directory
+/// name without `.git` (the parent's name for the `.bare` layout created by `gwt clone`).
+fn repo_name(repo_root: &Path) -> String {
+    let name = |p: &Path| {
+        p.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let dir_name = name(repo_root);
+    if dir_name == ".bare" {
+        return repo_root.parent().map(name).unwrap_or(dir_name);
+    }
+    dir_name
+        .strip_suffix(".git")
+        .map(str::to_string)
+        .unwrap_or(dir_name)
+}
+
+fn home_dir() -> Result<PathBuf, Error> {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| Error::new(ErrorCode::Config, "HOME is not set"))
+}
+
+/// Resolves `.` and `..` lexically, since the worktree directory usually doesn't exist yet.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Keeps worktrees placed inside the main worktree (e.g. `.worktrees/`) out of `git status` by
+/// adding their top-level directory to `info/exclude`.
+pub fn exclude_if_nested(worktree_path: &Path) -> Result<(), Error> {
+    let repo_root = get_repo_root()?;
+    let Ok(relative) = worktree_path.strip_prefix(&repo_root) else {
+        return Ok(());
+    };
+    let Some(Component::Normal(top_level)) = relative.components().next() else {
+        return Ok(());
+    };
+    exclude(&format!("/{}/", top_level.to_string_lossy()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Inverse of [`branch_slug`].
+    fn unslug(slug: &str) -> String {
+        let mut branch = String::new();
+        let mut rest = slug;
+        while let Some(c) = rest.chars().next() {
+            if let Some(after) = rest.strip_prefix("%25") {
+                branch.push('%');
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("%5F") {
+                branch.push('_');
+                rest = after;
+            } else {
+                branch.push(if c == '_' { '/' } else { c });
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        branch
+    }
+
+    #[test]
+    fn branch_slug_replaces_slashes_and_escapes() {
+        assert_eq!(branch_slug("feature/login"), "feature_login");
+        assert_eq!(branch_slug("feature_login"), "feature%5Flogin");
+        assert_eq!(branch_slug("100%"), "100%25");
+        assert_eq!(branch_slug("a%5Fb"), "a%255Fb");
+    }
+
+    #[test]
+    fn branch_slug_is_reversible_and_collision_free() {
+        let branches = [
+            "main",
+            "feature/login",
+            "feature_login",
+            "feature%5Flogin",
+            "feature%2Flogin",
+            "a/b_c%d",
+            "a_b/c%25d",
+            "%",
+            "_",
+            "/",
+        ];
+        for branch in branches {
+            assert_eq!(unslug(&branch_slug(branch)), branch);
+        }
+        let mut slugs = branches.map(branch_slug).to_vec();
+        slugs.sort();
+        slugs.dedup();
+        assert_eq!(slugs.len(), branches.len());
+    }
+
+    #[test]
+    fn expand_template_substitutes_variables() {
+        assert_eq!(
+            expand_template("../{repo}-{branch_slug}", "gwt", "feature/login").unwrap(),
+            "../gwt-feature_login"
+        );
+        assert_eq!(
+            expand_template("~/wt/{repo}/{branch}", "gwt", "feature/login").unwrap(),
+            "~/wt/gwt/feature/login"
+        );
+        assert_eq!(expand_template("plain", "gwt", "main").unwrap(), "plain");
+    }
+
+    #[test]
+    fn expand_template_rejects_unknown_variables() {
+        let error = expand_template("../{user}/{branch}", "gwt", "main").unwrap_err();
+        assert_eq!(error.code, ErrorCode::Config);
+        assert!(error.message.contains("unknown variable '{user}'"));
+    }
+
+    #[test]
+    fn expand_template_rejects_unclosed_braces() {
+        let error = expand_template("../{branch", "gwt", "main").unwrap_err();
+        assert_eq!(error.code, ErrorCode::Config);
+        assert!(error.message.contains("unclosed '{'"));
+    }
+
+    #[test]
+    fn normalize_resolves_dot_segments() {
+        assert_eq!(
+            normalize(Path::new("/repo/main/../feature_login")),
+            PathBuf::from("/repo/feature_login")
+        );
+        assert_eq!(
+            normalize(Path::new("/repo/main/./a/../../b")),
+            PathBuf::from("/repo/b")
+        );
+        assert_eq!(normalize(Path::new("/../a")), PathBuf::from("/a"));
+    }
+}
@@ -1,16 +1,32 @@
 use clap::{Parser, Subcommand};
 use colored::Colorize;
+use std::path::PathBuf;
 
 mod commands;
 mod config;
 mod direnv_utils;
+mod error;
 mod file_ops;
 mod git_utils;
+mod hooks;
+mod layout;
+mod output;
+mod patterns;
+mod picker;
+mod registry;
+mod safety;
+mod shell;
+mod store;
+mod sync;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 /// gwt (git work-tree) is an opininated CLI tool that should help managing git work-trees
 struct Cli {
+    /// Print a single JSON document describing the result instead of human readable output
+    #[arg(long, global = true)]
+    json: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -19,28 +35,125 @@ struct Cli {
 enum Commands {
     /// Adds a new git worktree
     Add {
-        /// The name of the branch to create a worktree for
+        /// The name of the branch to create a worktree for (a commit-ish with --detach)
         branch_name: String,
 
         /// Copy files instead of creating symbolic links (linking is default)
-        #[arg(short, long, default_value_t = false)]
+        #[arg(short, long, overrides_with = "no_copy")]
         copy: bool,
 
+        /// Create symbolic links even if defaults.copy is set
+        #[arg(long, overrides_with = "copy")]
+        no_copy: bool,
+
         /// Enable verbose output
-        #[arg(short, long)]
+        #[arg(short, long, overrides_with = "no_verbose")]
         verbose: bool,
 
+        /// Disable verbose output even if defaults.verbose is set
+        #[arg(long, overrides_with = "verbose")]
+        no_verbose: bool,
+
         /// Run git pull internally before creating worktree
-        #[arg(short, long)]
+        #[arg(short, long, overrides_with = "no_pull")]
         pull: bool,
+
+        /// Don't run git pull even if defaults.pull is set
+        #[arg(long, overrides_with = "pull")]
+        no_pull: bool,
+
+        /// Create the new branch from this ref instead of the remote's default branch
+        #[arg(short, long)]
+        base: Option<String>,
+
+        /// Fetch the remote of the base ref before creating the branch
+        #[arg(short, long, overrides_with = "no_fetch")]
+        fetch: bool,
+
+        /// Don't fetch even if defaults.fetch is set
+        #[arg(long, overrides_with = "fetch")]
+        no_fetch: bool,
+
+        /// Track the branch of this remote when several remotes have it
+        #[arg(short, long)]
+        remote: Option<String>,
+
+        /// Check out a tag or commit with a detached HEAD instead of a branch
+        #[arg(short, long, conflicts_with_all = ["base", "remote"])]
+        detach: bool,
+    },
+    /// Check out a pull/merge request into a new worktree named pr-<number>
+    Review {
+        /// The number of the pull request
+        number: u64,
+
+        /// The remote to fetch the pull request from (defaults to remote.name)
+        #[arg(short, long)]
+        remote: Option<String>,
     },
     /// Remove a new git worktree and the local branch
-    Remove { branch_name: String },
+    Remove {
+        /// Branches, names of detached worktrees or worktree paths to remove; picked
+        /// interactively if omitted
+        targets: Vec<String>,
+
+        /// Remove even if changes, unpushed commits or other work would be lost
+        #[arg(short, long)]
+        force: bool,
+
+        /// Only remove the worktree directory and keep the branch
+        #[arg(short, long)]
+        keep_branch: bool,
+    },
+    /// Rename a worktree's branch and move the worktree to match
+    Rename {
+        /// The branch (or name of a detached worktree) to rename
+        old: String,
+
+        /// The new branch name
+        new: String,
+
+        /// Make the branch track the new name on its remote instead of the old one
+        #[arg(short, long)]
+        update_upstream: bool,
+    },
+    /// Remove worktrees whose branch is merged, whose upstream is gone or that are unused
+    #[command(visible_alias = "cleanup")]
+    Prune {
+        /// Also remove worktrees that have not been used for this many days
+        #[arg(long, value_name = "DAYS")]
+        stale_days: Option<u64>,
+
+        /// Remove the worktrees without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Only show which worktrees would be removed
+        #[arg(short = 'n', long, conflicts_with = "yes")]
+        dry_run: bool,
+    },
     /// Sync files between worktrees
     Sync {
         /// Copy files instead of creating symbolic links (linking is default)
-        #[arg(short, long, default_value_t = false)]
+        #[arg(short, long, overrides_with = "no_copy")]
         copy: bool,
+
+        /// Create symbolic links even if defaults.copy is set
+        #[arg(long, overrides_with = "copy")]
+        no_copy: bool,
+
+        /// Resolve items changed in several worktrees by keeping this worktree's version
+        #[arg(long, value_name = "WORKTREE")]
+        prefer: Option<String>,
+
+        /// Only show what would be linked, copied or replaced in every worktree
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+    /// Move a file or directory into the shared store and link every worktree to it
+    Share {
+        /// The file or directory to share, relative to the current directory
+        path: PathBuf,
     },
     /// Clones a repository and sets it up for gwt worktree usage
     Clone {
@@ -49,25 +162,158 @@ enum Commands {
     },
     /// Initializes a .gwtconfig file based on .gitignore
     Init,
+    /// Manages the gwt configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Lists all worktrees with their branch, HEAD and status
+    List,
+    /// Changes into the worktree of a branch ('-' for the previous worktree)
+    Switch {
+        /// The branch whose worktree to switch to, or '-' for the previous worktree; picked interactively if omitted
+        branch_name: Option<String>,
+    },
+    /// Interactively picks a worktree and prints its path or runs a command in it
+    Pick {
+        /// Command to run inside the selected worktree
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Prints a shell function that lets 'gwt add' and 'gwt switch' change the directory
+    ShellInit {
+        /// The shell to generate the wrapper for
+        shell: shell::Shell,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Converts a plain .gwtconfig into a .gwt.toml
+    Migrate,
+    /// Prints the effective configuration (global config merged with the repository config)
+    Show {
+        /// Show the file each setting comes from
+        #[arg(long)]
+        origin: bool,
+    },
+}
+
+impl Commands {
+    fn name(&self) -> &'static str {
+        match self {
+            Commands::Add { .. } => "add",
+            Commands::Review { .. } => "review",
+            Commands::Remove { .. } => "remove",
+            Commands::Prune { .. } => "prune",
+            Commands::Rename { .. } => "rename",
+            Commands::Sync { .. } => "sync",
+            Commands::Share { .. } => "share",
+            Commands::Clone { .. } => "clone",
+            Commands::Init => "init",
+            Commands::Config { command } => match command {
+                ConfigCommands::Migrate => "config migrate",
+                ConfigCommands::Show { .. } => "config show",
+            },
+            Commands::List => "list",
+            Commands::Switch { .. } => "switch",
+            Commands::Pick { .. } => "pick",
+            Commands::ShellInit { .. } => "shell-init",
+        }
+    }
+}
+
+/// A `--flag` / `--no-flag` pair: `None` if neither is given, so the config decides.
+fn flag(on: bool, off: bool) -> Option<bool> {
+    match (on, off) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
 }
 
 fn main() {
     let cli = Cli::parse();
 
+    output::init(cli.command.name(), cli.json);
+
     let result = match &cli.command {
         Commands::Add {
             branch_name,
             copy,
+            no_copy,
             verbose,
+            no_verbose,
             pull,
-        } => commands::add_worktree(branch_name, *copy, *verbose, *pull),
-        Commands::Sync { copy } => commands::sync_worktrees(*copy),
+            no_pull,
+            base,
+            fetch,
+            no_fetch,
+            remote,
+            detach,
+        } => commands::add_worktree(
+            branch_name,
+            &commands::AddOptions {
+                copy: flag(*copy, *no_copy),
+                verbose: flag(*verbose, *no_verbose),
+                pull: flag(*pull, *no_pull),
+                base: base.as_deref(),
+                fetch: flag(*fetch, *no_fetch),
+                remote: remote.as_deref(),
+                detach: *detach,
+            },
+        ),
+        Commands::Review { number, remote } => commands::review(*number, remote.as_deref()),
+        Commands::Rename {
+            old,
+            new,
+            update_upstream,
+        } => commands::rename_worktree(old, new, *update_upstream),
+        Commands::Prune {
+            stale_days,
+            yes,
+            dry_run,
+        } => commands::prune_worktrees(&commands::PruneOptions {
+            stale_days: *stale_days,
+            yes: *yes,
+            dry_run: *dry_run,
+        }),
+        Commands::Sync {
+            copy,
+            no_copy,
+            prefer,
+            dry_run,
+        } => commands::sync_worktrees(flag(*copy, *no_copy), prefer.as_deref(), *dry_run),
+        Commands::Share { path } => commands::share(path),
         Commands::Clone { repo } => commands::clone_repo(repo),
         Commands::Init => commands::init_gwtconfig(),
-        Commands::Remove { branch_name } => commands::remove_worktree(branch_name),
+        Commands::Config { command } => match command {
+            ConfigCommands::Migrate => commands::migrate_config(),
+            ConfigCommands::Show { origin } => commands::show_config(*origin),
+        },
+        Commands::Remove {
+            targets,
+            force,
+            keep_branch,
+        } => commands::remove_worktrees(
+            targets,
+            &commands::RemoveOptions {
+                force: *force,
+                keep_branch: *keep_branch,
+            },
+        ),
+        Commands::List => commands::list_worktrees(),
+        Commands::Switch { branch_name } => commands::switch_worktree(branch_name.as_deref()),
+        Commands::Pick { command } => commands::pick(command),
+        Commands::ShellInit { shell } => commands::shell_init(*shell),
     };
 
+    output::finish(&result);
+
     if let Err(e) = result {
+        if cli.json {
+            std::process::exit(1);
+        }
         eprintln!("{} {e}", "Error:".red().bold());
         std::process::exit(1);
     }
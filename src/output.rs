@@ -0,0 +1,151 @@
+use colored::*;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt::Display;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Mutex;
+
+use crate::error::Error;
+
+/// Collected result of a command in `--json` mode. `None` means human output.
+static REPORT: Mutex<Option<Report>> = Mutex::new(None);
+
+#[derive(Serialize)]
+struct Report {
+    command: String,
+    ok: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    created: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    items: Vec<Item>,
+    warnings: Vec<String>,
+    #[serde(flatten)]
+    fields: Map<String, Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Error>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Linked,
+    Copied,
+    Skipped,
+}
+
+#[derive(Serialize)]
+struct Item {
+    path: String,
+    action: Action,
+    worktree: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+pub fn init(command: &str, json: bool) {
+    if !json {
+        return;
+    }
+    colored::control::set_override(false);
+    *REPORT.lock().unwrap() = Some(Report {
+        command: command.to_string(),
+        ok: true,
+        created: Vec::new(),
+        items: Vec::new(),
+        warnings: Vec::new(),
+        fields: Map::new(),
+        error: None,
+    });
+}
+
+pub fn is_json() -> bool {
+    REPORT.lock().unwrap().is_some()
+}
+
+/// Prints a plain line in human mode; dropped in JSON mode.
+pub fn text(message: impl Display) {
+    if !is_json() {
+        println!("{message}");
+    }
+}
+
+pub fn info(message: impl Display) {
+    if !is_json() {
+        println!("{} {message}", "Info:".green());
+    }
+}
+
+pub fn warn(message: impl Display) {
+    match REPORT.lock().unwrap().as_mut() {
+        Some(report) => report.warnings.push(message.to_string()),
+        None => eprintln!("{} {message}", "Warning:".yellow()),
+    }
+}
+
+/// Records what happened to a managed item in a worktree. `message` is what a human sees.
+pub fn item(action: Action, path: &str, worktree: &Path, message: impl Display) {
+    match REPORT.lock().unwrap().as_mut() {
+        Some(report) => report.items.push(Item {
+            path: path.to_string(),
+            action,
+            worktree: worktree.to_path_buf(),
+            reason: (action == Action::Skipped).then(|| message.to_string()),
+        }),
+        None if action == Action::Skipped => eprintln!("{} {message}", "Warning:".yellow()),
+        None => println!("{} {message}", "Info:".green()),
+    }
+}
+
+pub fn created(path: &Path) {
+    if let Some(report) = REPORT.lock().unwrap().as_mut() {
+        report.created.push(path.to_path_buf());
+    }
+}
+
+/// Sets a command specific top-level field of the JSON document.
+pub fn field(key: &str, value: impl Serialize) {
+    if let Some(report) = REPORT.lock().unwrap().as_mut() {
+        let value = serde_json::to_value(value).unwrap_or(Value::Null);
+        report.fields.insert(key.to_string(), value);
+    }
+}
+
+/// Where child processes should write their stdout, so they don't corrupt the JSON document.
+pub fn child_stdout() -> Stdio {
+    if is_json() {
+        Stdio::from(io::stderr())
+    } else {
+        Stdio::inherit()
+    }
+}
+
+/// Prints a prompt without a trailing newline; goes to stderr in JSON mode.
+pub fn prompt(message: impl Display) -> Result<(), String> {
+    use std::io::Write;
+
+    if is_json() {
+        eprint!("{message}");
+        io::stderr().flush()
+    } else {
+        print!("{message}");
+        io::stdout().flush()
+    }
+    .map_err(|e| format!("Failed to flush stdout: {e}"))
+}
+
+/// Emits the JSON document (if enabled) for the result of the command.
+pub fn finish(result: &Result<(), Error>) {
+    let Some(mut report) = REPORT.lock().unwrap().take() else {
+        return;
+    };
+    if let Err(e) = result {
+        report.ok = false;
+        report.error = Some(e.clone());
+    }
+    match serde_json::to_string_pretty(&report) {
+        Ok(json) => println!("{json}"),
+        Err(e) => eprintln!("Failed to serialize JSON output: {e}"),
+    }
+}
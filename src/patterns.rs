@@ -0,0 +1,375 @@
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+
+use crate::config::{Entry, Mode};
+use crate::error::{Error, ErrorCode};
+
+/// A path (relative to the worktree root) managed by gwt.
+#[derive(Debug, Clone)]
+pub struct ManagedItem {
+    pub path: String,
+    /// Mode of the entry that matched the path; `None` means the command's default mode
+    pub mode: Option<Mode>,
+}
+
+/// A single `.gwtconfig` entry, interpreted like a line of a `.gitignore`.
+struct Pattern {
+    negated: bool,
+    mode: Option<Mode>,
+    dir_only: bool,
+    regex: Regex,
+}
+
+impl Pattern {
+    fn parse(entry: &Entry) -> Result<Self, Error> {
+        let (negated, pattern) = match entry.pattern.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, entry.pattern.as_str()),
+        };
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+
+        // Like gitignore, a pattern with a slash (other than a trailing one) is anchored to the
+        // root; globs without a slash match at any depth. Plain paths are always anchored.
+        let anchored = pattern.contains('/') || !is_glob(pattern);
+        let pattern = pattern.trim_start_matches('/');
+
+        let mut regex = String::from("^");
+        if !anchored {
+            regex.push_str("(?:.*/)?");
+        }
+        regex.push_str(&glob_to_regex(pattern));
+        regex.push('$');
+
+        let regex = Regex::new(&regex).map_err(|e| {
+            Error::new(
+                ErrorCode::Config,
+                format!("Invalid pattern '{}' in .gwtconfig: {e}", entry.pattern),
+            )
+        })?;
+        Ok(Pattern {
+            negated,
+            mode: entry.mode,
+            dir_only,
+            regex,
+        })
+    }
+
+    fn matches(&self, relative_path: &str, is_dir: bool) -> bool {
+        (!self.dir_only || is_dir) && self.regex.is_match(relative_path)
+    }
+}
+
+fn is_glob(entry: &str) -> bool {
+    entry.contains(['*', '?', '['])
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut regex = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                if chars.get(i + 2) == Some(&'/') {
+                    regex.push_str("(?:.*/)?");
+                    i += 3;
+                } else {
+                    regex.push_str(".*");
+                    i += 2;
+                }
+                continue;
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' if chars[i + 1..].contains(&']') => {
+                regex.push('[');
+                i += 1;
+                if matches!(chars[i], '!' | '^') {
+                    regex.push('^');
+                    i += 1;
+                }
+                while chars[i] != ']' {
+                    if matches!(chars[i], '\\' | '[') {
+                        regex.push('\\');
+                    }
+                    regex.push(chars[i]);
+                    i += 1;
+                }
+                regex.push(']');
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    regex
+}
+
+/// Expands `.gwtconfig` entries against `root` into the list of relative paths gwt manages.
+///
+/// Entries are evaluated in order and the last matching entry wins, so `!` entries can exclude
+/// paths matched by earlier entries. Paths without glob characters are returned even if they
+/// don't exist, so callers can report them as missing. A directory is returned as a whole
+/// unless something below it is excluded, in which case its remaining children are returned.
+pub fn expand(root: &Path, entries: &[Entry]) -> Result<Vec<ManagedItem>, Error> {
+    let patterns = entries
+        .iter()
+        .map(Pattern::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let plain = |entry: &&Entry| !is_glob(&entry.pattern) && !entry.pattern.starts_with('!');
+    if entries.iter().all(|e| plain(&e)) {
+        return Ok(entries
+            .iter()
+            .map(|entry| ManagedItem {
+                path: entry.pattern.trim_end_matches('/').to_string(),
+                mode: entry.mode,
+            })
+            .collect());
+    }
+
+    let mut expanded = Vec::new();
+    collect(root, root, &patterns, None, &mut expanded)?;
+
+    // Plain paths that don't exist are kept so they can be reported as missing.
+    for entry in entries.iter().filter(plain) {
+        let path = entry.pattern.trim_end_matches('/');
+        if root.join(path).symlink_metadata().is_err() && !expanded.iter().any(|e| e.path == path) {
+            if let Some(Included(mode)) = state(&patterns, path, entry.pattern.ends_with('/')) {
+                expanded.push(ManagedItem {
+                    path: path.to_string(),
+                    mode,
+                });
+            }
+        }
+    }
+    Ok(expanded)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Included(Option<Mode>),
+    Excluded,
+}
+use State::{Excluded, Included};
+
+/// The state of the last pattern matching the path, `None` if no pattern matches.
+fn state(patterns: &[Pattern], relative_path: &str, is_dir: bool) -> Option<State> {
+    patterns
+        .iter()
+        .rev()
+        .find(|p| p.matches(relative_path, is_dir))
+        .map(|p| {
+            if p.negated {
+                Excluded
+            } else {
+                Included(p.mode)
+            }
+        })
+}
+
+fn collect(
+    root: &Path,
+    dir: &Path,
+    patterns: &[Pattern],
+    inherited: Option<State>,
+    expanded: &mut Vec<ManagedItem>,
+) -> Result<(), Error> {
+    let mut children = fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read directory {}: {e}", dir.display()))?
+        .filter_map(Result::ok)
+        .collect::<Vec<_>>();
+    children.sort_by_key(|c| c.file_name());
+
+    for child in children {
+        let path = child.path();
+        let Some(relative) = relative_path(root, &path) else {
+            continue;
+        };
+        // Never descend into git metadata or nested worktrees/repositories.
+        if child.file_name() == ".git" {
+            continue;
+        }
+        let is_dir = child.file_type().is_ok_and(|t| t.is_dir());
+        if is_dir && path.join(".git").exists() {
+            continue;
+        }
+
+        let state = state(patterns, &relative, is_dir).or(inherited);
+        match state {
+            Some(Included(mode)) if !is_dir || !has_excluded_descendant(root, &path, patterns) => {
+                expanded.push(ManagedItem {
+                    path: relative,
+                    mode,
+                });
+            }
+            _ if is_dir => collect(root, &path, patterns, state, expanded)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn has_excluded_descendant(root: &Path, dir: &Path, patterns: &[Pattern]) -> bool {
+    if !patterns.iter().any(|p| p.negated) {
+        return false;
+    }
+    WalkDir::new(dir)
+        .min_depth(1)
+        .into_iter()
+        .filter_map(Result::ok)
+        .any(|entry| {
+            relative_path(root, entry.path()).is_some_and(|relative| {
+                state(patterns, &relative, entry.file_type().is_dir()) == Some(Excluded)
+            })
+        })
+}
+
+fn relative_path(root: &Path, path: &Path) -> Option<String> {
+    path.strip_prefix(root)
+        .ok()
+        .map(|p| p.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(line: &str) -> Pattern {
+        Pattern::parse(&Entry::parse(line)).unwrap()
+    }
+
+    fn paths(items: &[ManagedItem]) -> Vec<&str> {
+        items.iter().map(|i| i.path.as_str()).collect()
+    }
+
+    #[test]
+    fn glob_to_regex_translates_wildcards() {
+        assert_eq!(glob_to_regex("*.env"), r"[^/]*\.env");
+        assert_eq!(glob_to_regex("a?c"), "a[^/]c");
+        assert_eq!(glob_to_regex("**/cache"), "(?:.*/)?cache");
+        assert_eq!(glob_to_regex("logs/**"), "logs/.*");
+        assert_eq!(glob_to_regex("[!a-c]x"), "[^a-c]x");
+        assert_eq!(glob_to_regex("[^a]"), "[^a]");
+        assert_eq!(glob_to_regex("a[b"), r"a\[b");
+    }
+
+    #[test]
+    fn globs_without_slash_match_at_any_depth() {
+        let p = pattern("*.env");
+        assert!(p.matches(".env", false));
+        assert!(p.matches("config/local.env", false));
+        assert!(!p.matches("config/local.env.bak", false));
+    }
+
+    #[test]
+    fn globs_with_slash_and_plain_paths_are_anchored() {
+        let p = pattern("config/*.env");
+        assert!(p.matches("config/a.env", false));
+        assert!(!p.matches("sub/config/a.env", false));
+        assert!(!p.matches("config/nested/a.env", false));
+
+        let p = pattern(".env");
+        assert!(p.matches(".env", false));
+        assert!(!p.matches("sub/.env", false));
+
+        let p = pattern("/*.db");
+        assert!(p.matches("dev.db", false));
+        assert!(!p.matches("data/dev.db", false));
+    }
+
+    #[test]
+    fn double_star_matches_any_number_of_directories() {
+        let p = pattern("**/cache");
+        assert!(p.matches("cache", true));
+        assert!(p.matches("a/b/cache", true));
+
+        let p = pattern("a/**/z");
+        assert!(p.matches("a/z", false));
+        assert!(p.matches("a/b/c/z", false));
+    }
+
+    #[test]
+    fn negated_character_class() {
+        let p = pattern("[!.]*.txt");
+        assert!(p.matches("notes.txt", false));
+        assert!(!p.matches(".notes.txt", false));
+    }
+
+    #[test]
+    fn trailing_slash_only_matches_directories() {
+        let p = pattern("build/");
+        assert!(p.dir_only);
+        assert!(p.matches("build", true));
+        assert!(!p.matches("build", false));
+    }
+
+    #[test]
+    fn negation_and_mode_prefixes_are_parsed() {
+        let p = pattern("!node_modules/.cache");
+        assert!(p.negated);
+        let p = pattern("skip:node_modules/.cache");
+        assert!(p.negated);
+        let p = pattern("copy:.env");
+        assert!(!p.negated);
+        assert_eq!(p.mode, Some(Mode::Copy));
+    }
+
+    #[test]
+    fn malformed_character_class_is_an_error() {
+        assert!(Pattern::parse(&Entry::parse("[]")).is_err());
+        assert!(Pattern::parse(&Entry::parse("[!]")).is_err());
+        assert!(Pattern::parse(&Entry::parse("a[")).is_ok());
+    }
+
+    #[test]
+    fn plain_entries_are_returned_without_reading_the_directory() {
+        let entries = [Entry::parse("link:.env"), Entry::parse("cache/")];
+        let items = expand(Path::new("/nonexistent/gwt-test"), &entries).unwrap();
+        assert_eq!(paths(&items), [".env", "cache"]);
+        assert_eq!(items[0].mode, Some(Mode::Link));
+        assert_eq!(items[1].mode, None);
+    }
+
+    #[test]
+    fn negation_expands_a_directory_into_its_remaining_children() {
+        let root = tempfile::tempdir().unwrap();
+        let modules = root.path().join("node_modules");
+        for dir in [".cache", "left-pad", "react"] {
+            fs::create_dir_all(modules.join(dir)).unwrap();
+        }
+        fs::write(root.path().join(".env"), "").unwrap();
+
+        let entries = [
+            Entry::parse("node_modules"),
+            Entry::parse("!node_modules/.cache"),
+            Entry::parse(".env"),
+            Entry::parse("missing.txt"),
+        ];
+        let items = expand(root.path(), &entries).unwrap();
+        assert_eq!(
+            paths(&items),
+            [
+                ".env",
+                "node_modules/left-pad",
+                "node_modules/react",
+                "missing.txt"
+            ]
+        );
+    }
+
+    #[test]
+    fn globs_are_matched_against_the_tree() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("config")).unwrap();
+        fs::create_dir_all(root.path().join(".git")).unwrap();
+        for file in ["a.env", "config/b.env", "config/c.txt", ".git/d.env"] {
+            fs::write(root.path().join(file), "").unwrap();
+        }
+
+        let items = expand(root.path(), &[Entry::parse("*.env")]).unwrap();
+        assert_eq!(paths(&items), ["a.env", "config/b.env"]);
+    }
+}
@@ -0,0 +1,205 @@
+use colored::*;
+use std::io::{self, IsTerminal, Read, Write};
+use std::process::{Command, Stdio};
+
+use crate::error::{Error, ErrorCode};
+use crate::git_utils::{self, Worktree};
+
+const MAX_VISIBLE: usize = 10;
+
+struct Entry {
+    worktree: Worktree,
+    label: String,
+    subject: String,
+    age: String,
+}
+
+impl Entry {
+    fn matches(&self, query: &str) -> bool {
+        let haystack = format!(
+            "{} {} {}",
+            self.label,
+            self.subject,
+            self.worktree.path.display()
+        )
+        .to_lowercase();
+        let mut haystack = haystack.chars();
+        query
+            .to_lowercase()
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .all(|c| haystack.any(|h| h == c))
+    }
+}
+
+/// Lets the user interactively pick one of the (non-bare) worktrees.
+pub fn pick_worktree(prompt: &str) -> Result<Worktree, Error> {
+    if !io::stdin().is_terminal() {
+        return Err(Error::new(
+            ErrorCode::NotATerminal,
+            "The interactive picker needs a terminal; pass the branch name explicitly instead",
+        ));
+    }
+
+    let mut entries = Vec::new();
+    for worktree in git_utils::get_worktrees()? {
+        if worktree.bare || worktree.prunable.is_some() {
+            continue;
+        }
+        let label = match &worktree.branch {
+            Some(branch) => branch.clone(),
+            None => {
+                let head: String = worktree
+                    .head
+                    .iter()
+                    .flat_map(|h| h.chars())
+                    .take(7)
+                    .collect();
+                format!("(detached {head})")
+            }
+        };
+        let (subject, age) = git_utils::last_commit(&worktree.path)?.unwrap_or_default();
+        entries.push(Entry {
+            worktree,
+            label,
+            subject,
+            age,
+        });
+    }
+
+    if entries.is_empty() {
+        return Err(Error::new(
+            ErrorCode::NotFound,
+            "There are no worktrees to pick from",
+        ));
+    }
+
+    let saved_mode = stty(&["-g"])?;
+    stty(&["raw", "-echo"])?;
+    let selection = run(prompt, &entries);
+    let _ = stty(&[saved_mode.trim()]);
+    eprint!("\r\x1b[J");
+    let _ = io::stderr().flush();
+
+    let index = selection?;
+    Ok(entries.swap_remove(index).worktree)
+}
+
+fn run(prompt: &str, entries: &[Entry]) -> Result<usize, Error> {
+    let width = terminal_width();
+    let mut query = String::new();
+    let mut selected = 0;
+    let mut stdin = io::stdin();
+    let mut buf = [0u8; 16];
+
+    loop {
+        let filtered: Vec<usize> = (0..entries.len())
+            .filter(|&i| entries[i].matches(&query))
+            .collect();
+        selected = selected.min(filtered.len().saturating_sub(1));
+        render(prompt, &query, entries, &filtered, selected, width)?;
+
+        let n = stdin
+            .read(&mut buf)
+            .map_err(|e| format!("Failed to read from terminal: {e}"))?;
+        match &buf[..n] {
+            // Enter
+            [b'\r'] => {
+                if let Some(&index) = filtered.get(selected) {
+                    return Ok(index);
+                }
+            }
+            // Escape, Ctrl-C, Ctrl-D
+            [0x1b] | [0x03] | [0x04] | [] => {
+                return Err(Error::new(ErrorCode::Cancelled, "No worktree selected"));
+            }
+            // Up arrow, Ctrl-P, Ctrl-K
+            [0x1b, b'[', b'A'] | [0x1b, b'O', b'A'] | [0x10] | [0x0b] => {
+                selected = selected.saturating_sub(1);
+            }
+            // Down arrow, Ctrl-N, Ctrl-J
+            [0x1b, b'[', b'B'] | [0x1b, b'O', b'B'] | [0x0e] | [0x0a] => {
+                if selected + 1 < filtered.len() {
+                    selected += 1;
+                }
+            }
+            // Backspace
+            [0x7f] | [0x08] => {
+                query.pop();
+            }
+            // Ctrl-U
+            [0x15] => query.clear(),
+            bytes => {
+                if let Ok(text) = std::str::from_utf8(bytes) {
+                    query.extend(text.chars().filter(|c| !c.is_control()));
+                }
+            }
+        }
+    }
+}
+
+fn render(
+    prompt: &str,
+    query: &str,
+    entries: &[Entry],
+    filtered: &[usize],
+    selected: usize,
+    width: usize,
+) -> Result<(), String> {
+    let label_width = filtered
+        .iter()
+        .map(|&i| entries[i].label.chars().count())
+        .max()
+        .unwrap_or(0);
+    let start = selected.saturating_sub(MAX_VISIBLE - 1);
+
+    let mut screen = format!(
+        "\r\x1b[J{} {query}\r\n",
+        format!("{prompt} ({}/{})>", filtered.len(), entries.len()).cyan()
+    );
+    let visible = &filtered[start..filtered.len().min(start + MAX_VISIBLE)];
+    for (offset, &i) in visible.iter().enumerate() {
+        let entry = &entries[i];
+        let line = format!(
+            "{:<label_width$}  {} ({})",
+            entry.label, entry.subject, entry.age
+        );
+        let line: String = line.chars().take(width.saturating_sub(2)).collect();
+        if start + offset == selected {
+            screen.push_str(&format!("{} {}\r\n", ">".green().bold(), line.bold()));
+        } else {
+            screen.push_str(&format!("  {line}\r\n"));
+        }
+    }
+    // Move the cursor back behind the query on the prompt line.
+    let prompt_len = format!("{prompt} ({}/{})> {query}", filtered.len(), entries.len())
+        .chars()
+        .count();
+    screen.push_str(&format!("\x1b[{}A\r\x1b[{prompt_len}C", visible.len() + 1));
+
+    let mut stderr = io::stderr();
+    stderr
+        .write_all(screen.as_bytes())
+        .and_then(|_| stderr.flush())
+        .map_err(|e| format!("Failed to write to terminal: {e}"))
+}
+
+fn stty(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("Failed to execute stty: {e}"))?;
+    if !output.status.success() {
+        return Err("Failed to configure the terminal".to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn terminal_width() -> usize {
+    stty(&["size"])
+        .ok()
+        .and_then(|size| size.split_whitespace().nth(1)?.parse().ok())
+        .filter(|&width| width > 0)
+        .unwrap_or(80)
+}
@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::error::{Error, ErrorCode};
+use crate::git_utils::{get_default_branch, get_gwt_dir, get_repo_root, get_worktrees, Worktree};
+
+const REGISTRY_FILE: &str = "worktrees.json";
+
+/// The worktrees created by gwt, keyed by branch name (or by the ref they were created from for
+/// detached worktrees). Stored in the git common dir, so every worktree of the repository sees
+/// the same registry.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Registry {
+    #[serde(default)]
+    pub worktrees: BTreeMap<String, Record>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Record {
+    pub path: PathBuf,
+    /// Seconds since the Unix epoch
+    #[serde(default)]
+    pub created: u64,
+    /// Created with `gwt add --detach`; there is no branch to delete on removal
+    #[serde(default)]
+    pub detached: bool,
+    /// The commit a branch created by `gwt add` started from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_commit: Option<String>,
+    /// Number of the pull request checked out with `gwt review`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_request: Option<u64>,
+}
+
+impl Registry {
+    pub fn load() -> Result<Registry, Error> {
+        let path = get_gwt_dir()?.join(REGISTRY_FILE);
+        if !path.exists() {
+            return Ok(Registry::default());
+        }
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        serde_json::from_str(&content).map_err(|e| {
+            Error::new(
+                ErrorCode::Config,
+                format!("Failed to parse {}: {e}", path.display()),
+            )
+        })
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let path = get_gwt_dir()?.join(REGISTRY_FILE);
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize the worktree registry: {e}"))?;
+        fs::write(&path, format!("{content}\n"))
+            .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+        Ok(())
+    }
+
+    /// Registers a worktree, replacing stale records of worktrees that used the same path.
+    pub fn insert(&mut self, name: &str, mut record: Record) {
+        record.created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        self.worktrees
+            .retain(|_, existing| !same_path(&existing.path, &record.path));
+        self.worktrees.insert(name.to_string(), record);
+    }
+
+    /// Re-registers the worktree `old_name` as `new_name` at `path`, keeping its record.
+    pub fn rename(&mut self, old_name: &str, new_name: &str, path: &Path) {
+        match self.worktrees.remove(old_name) {
+            Some(mut record) => {
+                record.path = path.to_path_buf();
+                self.worktrees.insert(new_name.to_string(), record);
+            }
+            None => self.insert(
+                new_name,
+                Record {
+                    path: path.to_path_buf(),
+                    ..Record::default()
+                },
+            ),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Record> {
+        self.worktrees.remove(name)
+    }
+
+    /// The name the worktree at `path` is registered under, if any.
+    pub fn name_at(&self, path: &Path) -> Option<&str> {
+        self.worktrees
+            .iter()
+            .find(|(_, record)| same_path(&record.path, path))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// Finds the worktree registered as `name`, or else the worktree git has the branch `name`
+/// checked out in.
+pub fn find_worktree(name: &str) -> Result<Option<Worktree>, Error> {
+    let worktrees = get_worktrees()?;
+    let registry = Registry::load()?;
+
+    if let Some(record) = registry.worktrees.get(name) {
+        let registered = worktrees.iter().find(|w| same_path(&w.path, &record.path));
+        if let Some(worktree) = registered {
+            return Ok(Some(worktree.clone()));
+        }
+    }
+    Ok(worktrees
+        .into_iter()
+        .find(|w| w.branch.as_deref() == Some(name)))
+}
+
+/// Finds the worktree at `path`.
+pub fn find_worktree_at(path: &Path) -> Result<Option<Worktree>, Error> {
+    let Ok(path) = fs::canonicalize(path) else {
+        return Ok(None);
+    };
+    Ok(get_worktrees()?
+        .into_iter()
+        .find(|w| same_path(&w.path, &path)))
+}
+
+/// The worktree that holds the real copies of linked managed files, so links don't depend on
+/// where gwt runs: `files.primary` if set, else the worktree of the remote's default branch, else
+/// the main worktree (or, in a bare repository, the oldest worktree).
+pub fn primary_worktree(config: &Config) -> Result<PathBuf, Error> {
+    let worktrees = get_worktrees()?
+        .into_iter()
+        .filter(|w| !w.bare && w.prunable.is_none())
+        .collect::<Vec<_>>();
+
+    if let Some(primary) = &config.primary {
+        let found = match find_worktree(primary)?.filter(|w| !w.bare) {
+            Some(worktree) => Some(worktree),
+            None => find_worktree_at(&get_repo_root()?.join(primary))?,
+        };
+        return found.map(|w| w.path).ok_or_else(|| {
+            Error::new(
+                ErrorCode::Config,
+                format!("files.primary is '{primary}', but there is no such worktree"),
+            )
+        });
+    }
+
+    if let Ok(default_branch) = get_default_branch(&config.remote.name) {
+        let default_worktree = worktrees
+            .iter()
+            .find(|w| w.branch.as_deref() == Some(default_branch.as_str()));
+        if let Some(worktree) = default_worktree {
+            return Ok(worktree.path.clone());
+        }
+    }
+    worktrees
+        .iter()
+        .find(|w| w.main)
+        .or(worktrees.first())
+        .map(|w| w.path.clone())
+        .ok_or_else(|| {
+            Error::new(
+                ErrorCode::NotFound,
+                "There is no worktree to hold the managed files",
+            )
+        })
+}
+
+/// Makes sure a new worktree named `branch_name` at `path` doesn't clash with an existing
+/// worktree or directory.
+pub fn check_collision(branch_name: &str, path: &Path) -> Result<(), Error> {
+    let worktrees = get_worktrees()?;
+    let registry = Registry::load()?;
+    if let Some(record) = registry.worktrees.get(branch_name) {
+        if worktrees.iter().any(|w| same_path(&w.path, &record.path)) {
+            return Err(Error::new(
+                ErrorCode::AlreadyExists,
+                format!(
+                    "A worktree named '{branch_name}' already exists in {}",
+                    record.path.display()
+                ),
+            ));
+        }
+    }
+    if let Some(existing) = worktrees
+        .iter()
+        .find(|w| w.branch.as_deref() == Some(branch_name))
+    {
+        return Err(Error::new(
+            ErrorCode::AlreadyExists,
+            format!(
+                "Branch '{branch_name}' is already checked out in {}",
+                existing.path.display()
+            ),
+        ));
+    }
+    if let Some(existing) = worktrees.iter().find(|w| same_path(&w.path, path)) {
+        let owner = existing.branch.as_deref().unwrap_or("a detached HEAD");
+        return Err(Error::new(
+            ErrorCode::AlreadyExists,
+            format!(
+                "{} is already the worktree of {owner}, pick a different layout.path",
+                path.display()
+            ),
+        ));
+    }
+    if let Some(owner) = registry.name_at(path).filter(|_| path.exists()) {
+        if owner != branch_name {
+            return Err(Error::new(
+                ErrorCode::AlreadyExists,
+                format!("{} is registered for '{owner}'", path.display()),
+            ));
+        }
+    }
+    if path.symlink_metadata().is_ok() && !is_empty_dir(path) {
+        return Err(Error::new(
+            ErrorCode::AlreadyExists,
+            format!("{} already exists and is not empty", path.display()),
+        ));
+    }
+    Ok(())
+}
+
+fn is_empty_dir(path: &Path) -> bool {
+    fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_none())
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    let canonical = |p: &Path| fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf());
+    a == b || canonical(a) == canonical(b)
+}
@@ -0,0 +1,239 @@
+use std::fs;
+
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::error::Error;
+use crate::file_ops::same_content;
+use crate::git_utils::{self, Worktree};
+use crate::patterns;
+use crate::registry;
+use crate::store::Store;
+
+/// Something that would be lost by removing a worktree.
+#[derive(Debug, Serialize)]
+pub struct Problem {
+    pub kind: ProblemKind,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProblemKind {
+    Uncommitted,
+    Unpushed,
+    Stash,
+    Locked,
+    ModifiedCopy,
+    LinkTarget,
+}
+
+/// Refs whose commits are safe elsewhere besides remotes and branches: fetched pull requests.
+const GWT_REFS: &str = "--glob=refs/gwt";
+
+/// Checks everything that would be lost by removing `worktree` and, if `deletes_branch` is set,
+/// its branch: uncommitted changes (other than gwt's own managed symlinks), commits that exist
+/// nowhere else, stashes made on the branch, a lock, copied managed files that no other worktree
+/// has an identical copy of, and managed symlinks of other worktrees that would dangle.
+pub fn check_worktree(
+    config: &Config,
+    worktree: &Worktree,
+    deletes_branch: bool,
+) -> Result<Vec<Problem>, Error> {
+    let mut problems = Vec::new();
+    let mut problem = |kind, message: String| problems.push(Problem { kind, message });
+
+    if let Some(reason) = &worktree.locked {
+        let message = if reason.is_empty() {
+            "The worktree is locked".to_string()
+        } else {
+            format!("The worktree is locked: {reason}")
+        };
+        problem(ProblemKind::Locked, message);
+    }
+
+    // Symlinks gwt created show up as untracked unless they are ignored, but removing them
+    // loses nothing.
+    let links = managed_links(config, worktree)?;
+    let changes = git_utils::changed_files(&worktree.path)?
+        .into_iter()
+        .filter(|line| {
+            line.strip_prefix("?? ")
+                .is_none_or(|path| !links.contains(&path.trim_end_matches('/').to_string()))
+        })
+        .collect::<Vec<_>>();
+    if !changes.is_empty() {
+        problem(
+            ProblemKind::Uncommitted,
+            format!(
+                "{} uncommitted change(s): {}",
+                changes.len(),
+                summarize(&changes)
+            ),
+        );
+    }
+
+    match &worktree.branch {
+        Some(_) if !deletes_branch => {}
+        Some(branch) => {
+            let (count, location) = match git_utils::upstream(branch)? {
+                Some(upstream) => (
+                    git_utils::count_commits(&worktree.path, &[&format!("{upstream}..{branch}")])?,
+                    format!("pushed to {upstream}"),
+                ),
+                None => (
+                    git_utils::count_commits(
+                        &worktree.path,
+                        &[
+                            &format!("refs/heads/{branch}"),
+                            "--not",
+                            "--remotes",
+                            GWT_REFS,
+                            &format!("--exclude={branch}"),
+                            "--branches",
+                        ],
+                    )?,
+                    "on any remote or other branch".to_string(),
+                ),
+            };
+            if count > 0 {
+                problem(
+                    ProblemKind::Unpushed,
+                    format!("{count} commit(s) on {branch} not {location}"),
+                );
+            }
+
+            let stashes = git_utils::stashes_on_branch(branch)?;
+            if !stashes.is_empty() {
+                problem(
+                    ProblemKind::Stash,
+                    format!(
+                        "{} stash(es) made on {branch}: {}",
+                        stashes.len(),
+                        summarize(&stashes)
+                    ),
+                );
+            }
+        }
+        None => {
+            let count = git_utils::count_commits(
+                &worktree.path,
+                &[
+                    "HEAD",
+                    "--not",
+                    "--branches",
+                    "--remotes",
+                    "--tags",
+                    GWT_REFS,
+                ],
+            )?;
+            if count > 0 {
+                problem(
+                    ProblemKind::Unpushed,
+                    format!("{count} commit(s) only reachable from the detached HEAD"),
+                );
+            }
+        }
+    }
+
+    let others = git_utils::get_worktrees()?
+        .into_iter()
+        .filter(|w| !w.bare && w.prunable.is_none() && w.path != worktree.path)
+        .collect::<Vec<_>>();
+    for item in patterns::expand(&worktree.path, &config.files)? {
+        let copy = worktree.path.join(&item.path);
+        if copy.is_symlink() || copy.symlink_metadata().is_err() {
+            continue;
+        }
+        if !others
+            .iter()
+            .any(|other| same_content(&copy, &other.path.join(&item.path)))
+        {
+            problem(
+                ProblemKind::ModifiedCopy,
+                format!(
+                    "'{}' is a copy that differs from every other worktree",
+                    item.path
+                ),
+            );
+        }
+    }
+
+    if registry::primary_worktree(config).is_ok_and(|primary| primary == worktree.path) {
+        problem(
+            ProblemKind::LinkTarget,
+            "It is the primary worktree, which holds the managed files that are linked".to_string(),
+        );
+    }
+    let mut links = Vec::new();
+    for other in &others {
+        for item in patterns::expand(&other.path, &config.files)? {
+            let link = other.path.join(&item.path);
+            let Ok(target) = fs::read_link(&link) else {
+                continue;
+            };
+            let target = link
+                .parent()
+                .map_or(target.clone(), |parent| parent.join(&target));
+            if target.starts_with(&worktree.path) {
+                links.push(link.display().to_string());
+            }
+        }
+    }
+    if !links.is_empty() {
+        problem(
+            ProblemKind::LinkTarget,
+            format!(
+                "{} managed symlink(s) of other worktrees point into it: {}",
+                links.len(),
+                summarize(&links)
+            ),
+        );
+    }
+
+    Ok(problems)
+}
+
+/// The managed items of `worktree` that are symlinks gwt created, into the primary worktree or
+/// the shared store.
+pub fn managed_links(config: &Config, worktree: &Worktree) -> Result<Vec<String>, Error> {
+    let store = Store::load()?;
+    let link_roots = registry::primary_worktree(config)
+        .into_iter()
+        .filter(|primary| *primary != worktree.path)
+        .chain([store.root().to_path_buf()])
+        .collect::<Vec<_>>();
+    let items = patterns::expand(&worktree.path, &config.files)?
+        .into_iter()
+        .map(|item| item.path)
+        .chain(store.items.iter().cloned());
+    Ok(items
+        .filter(|item| {
+            fs::read_link(worktree.path.join(item))
+                .is_ok_and(|target| link_roots.iter().any(|root| target.starts_with(root)))
+        })
+        .collect())
+}
+
+fn summarize(lines: &[String]) -> String {
+    const SHOWN: usize = 5;
+    let mut summary = lines
+        .iter()
+        .take(SHOWN)
+        .map(|line| line.trim())
+        .collect::<Vec<_>>()
+        .join(", ");
+    if lines.len() > SHOWN {
+        summary.push_str(&format!(", and {} more", lines.len() - SHOWN));
+    }
+    summary
+}
+
+/// Formats the problems as an indented list below `heading`.
+pub fn describe(heading: &str, problems: &[Problem]) -> String {
+    let mut description = heading.to_string();
+    for problem in problems {
+        description.push_str(&format!("\n  - {}", problem.message));
+    }
+    description
+}
@@ -0,0 +1,94 @@
+use clap::ValueEnum;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use crate::git_utils::{get_git_root, get_gwt_dir};
+
+/// Environment variable set by the shell wrapper. gwt writes the directory to change into there.
+const CD_FILE_ENV: &str = "GWT_CD_FILE";
+const PREVIOUS_WORKTREE_FILE: &str = "previous-worktree";
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+pub fn init_script(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash | Shell::Zsh => {
+            r#"gwt() {
+    local gwt_cd_file gwt_status
+    gwt_cd_file="$(mktemp "${TMPDIR:-/tmp}/gwt-cd.XXXXXX")" || return
+    GWT_CD_FILE="$gwt_cd_file" command gwt "$@"
+    gwt_status=$?
+    if [ -s "$gwt_cd_file" ]; then
+        cd -- "$(cat -- "$gwt_cd_file")" || gwt_status=$?
+    fi
+    rm -f -- "$gwt_cd_file"
+    return $gwt_status
+}
+"#
+        }
+        Shell::Fish => {
+            r#"function gwt --wraps gwt --description 'gwt with automatic directory switching'
+    set -l gwt_cd_file (mktemp -t gwt-cd.XXXXXX); or return
+    GWT_CD_FILE=$gwt_cd_file command gwt $argv
+    set -l gwt_status $status
+    if test -s $gwt_cd_file
+        cd (cat $gwt_cd_file); or set gwt_status $status
+    end
+    rm -f $gwt_cd_file
+    return $gwt_status
+end
+"#
+        }
+    }
+}
+
+/// Asks the shell wrapper (if any) to change into `path` and remembers the worktree we came from.
+pub fn change_directory(path: &Path) -> Result<(), String> {
+    remember_current_worktree(path)?;
+
+    if let Ok(cd_file) = env::var(CD_FILE_ENV) {
+        fs::write(&cd_file, path.as_os_str().as_encoded_bytes())
+            .map_err(|e| format!("Failed to write {cd_file}: {e}"))?;
+    }
+    Ok(())
+}
+
+pub fn previous_worktree() -> Result<Option<String>, String> {
+    let previous_path = get_gwt_dir()?.join(PREVIOUS_WORKTREE_FILE);
+    if !previous_path.exists() {
+        return Ok(None);
+    }
+    let previous = fs::read_to_string(&previous_path)
+        .map_err(|e| format!("Failed to read {}: {e}", previous_path.display()))?;
+    Ok(Some(previous.trim_end_matches('\n').to_string()).filter(|p| !p.is_empty()))
+}
+
+/// Keeps `gwt switch -` working after the previous worktree moved from `from` to `to`.
+pub fn worktree_moved(from: &Path, to: &Path) -> Result<(), String> {
+    if previous_worktree()?.is_some_and(|previous| Path::new(&previous) == from) {
+        let previous_path = get_gwt_dir()?.join(PREVIOUS_WORKTREE_FILE);
+        fs::write(&previous_path, format!("{}\n", to.display()))
+            .map_err(|e| format!("Failed to write {}: {e}", previous_path.display()))?;
+    }
+    Ok(())
+}
+
+fn remember_current_worktree(target: &Path) -> Result<(), String> {
+    let Ok(current) = get_git_root() else {
+        return Ok(());
+    };
+    let current = fs::canonicalize(&current).unwrap_or(current);
+    if current == target {
+        return Ok(());
+    }
+
+    let previous_path = get_gwt_dir()?.join(PREVIOUS_WORKTREE_FILE);
+    fs::write(&previous_path, format!("{}\n", current.display()))
+        .map_err(|e| format!("Failed to write {}: {e}", previous_path.display()))
+}
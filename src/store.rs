@@ -0,0 +1,139 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, ErrorCode};
+use crate::git_utils::get_gwt_dir;
+
+const STORE_DIR: &str = "shared";
+const MANIFEST_FILE: &str = "shared.json";
+
+/// Managed files shared with `gwt share`. They live in `shared/` in the git common dir rather
+/// than in any worktree, and every worktree links to them, so removing a worktree never leaves
+/// the others with dangling symlinks.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Store {
+    /// Paths relative to the worktree root
+    #[serde(default)]
+    pub items: BTreeSet<String>,
+    #[serde(skip)]
+    root: PathBuf,
+}
+
+impl Store {
+    pub fn load() -> Result<Store, Error> {
+        let gwt_dir = get_gwt_dir()?;
+        let root = gwt_dir.join(STORE_DIR);
+        let path = gwt_dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(Store {
+                root,
+                ..Store::default()
+            });
+        }
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        let store: Store = serde_json::from_str(&content).map_err(|e| {
+            Error::new(
+                ErrorCode::Config,
+                format!("Failed to parse {}: {e}", path.display()),
+            )
+        })?;
+        Ok(Store { root, ..store })
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let path = get_gwt_dir()?.join(MANIFEST_FILE);
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize the shared store: {e}"))?;
+        fs::write(&path, format!("{content}\n"))
+            .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+        Ok(())
+    }
+
+    /// The directory holding the shared files.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Where the shared copy of `item` lives.
+    pub fn path(&self, item: &str) -> PathBuf {
+        self.root.join(item)
+    }
+
+    pub fn insert(&mut self, item: &str) {
+        self.items.insert(item.to_string());
+    }
+
+    /// The shared item that is `item` or contains it, if any.
+    pub fn covering(&self, item: &str) -> Option<&str> {
+        self.items
+            .iter()
+            .find(|shared| {
+                item == shared.as_str()
+                    || item
+                        .strip_prefix(shared.as_str())
+                        .is_some_and(|rest| rest.starts_with('/'))
+            })
+            .map(String::as_str)
+    }
+
+    /// A shared item inside `item`, if any.
+    pub fn within(&self, item: &str) -> Option<&str> {
+        self.items
+            .iter()
+            .find(|shared| {
+                shared
+                    .strip_prefix(item)
+                    .is_some_and(|rest| rest.starts_with('/'))
+            })
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(items: &[&str]) -> Store {
+        let mut store = Store::default();
+        for item in items {
+            store.insert(item);
+        }
+        store
+    }
+
+    #[test]
+    fn covering_finds_the_item_or_the_directory_containing_it() {
+        let store = store(&[".env", "config/local", "data"]);
+        assert_eq!(store.covering(".env"), Some(".env"));
+        assert_eq!(store.covering("data/db/file"), Some("data"));
+        assert_eq!(store.covering("config/local/a.ini"), Some("config/local"));
+        assert_eq!(store.covering("config"), None);
+        assert_eq!(store.covering(".env.local"), None);
+        assert_eq!(store.covering("database"), None);
+    }
+
+    #[test]
+    fn within_finds_shared_items_below_a_directory() {
+        let store = store(&["config/local", "data"]);
+        assert_eq!(store.within("config"), Some("config/local"));
+        assert_eq!(store.within("config/local"), None);
+        assert_eq!(store.within("conf"), None);
+        assert_eq!(store.within("data"), None);
+    }
+
+    #[test]
+    fn paths_are_below_the_root() {
+        let store = Store {
+            root: PathBuf::from("/repo/.git/gwt/shared"),
+            ..store(&[".env"])
+        };
+        assert_eq!(
+            store.path("config/local"),
+            Path::new("/repo/.git/gwt/shared/config/local")
+        );
+    }
+}
@@ -0,0 +1,850 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::config::Mode;
+use crate::error::{Error, ErrorCode};
+use crate::git_utils::get_gwt_dir;
+use crate::patterns::ManagedItem;
+use crate::store::Store;
+
+const STATE_FILE: &str = "sync-state.json";
+
+/// The content every managed item had after the last `gwt sync`, so the next sync can tell
+/// which worktrees changed it since. Stored in the git common dir next to the registry.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SyncState {
+    #[serde(default)]
+    pub items: BTreeMap<String, ItemState>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemState {
+    /// Content hash of the item, see [`hash`]
+    pub hash: String,
+}
+
+impl SyncState {
+    pub fn load() -> Result<SyncState, Error> {
+        let path = get_gwt_dir()?.join(STATE_FILE);
+        if !path.exists() {
+            return Ok(SyncState::default());
+        }
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        serde_json::from_str(&content).map_err(|e| {
+            Error::new(
+                ErrorCode::Config,
+                format!("Failed to parse {}: {e}", path.display()),
+            )
+        })
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let path = get_gwt_dir()?.join(STATE_FILE);
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize the sync state: {e}"))?;
+        fs::write(&path, format!("{content}\n"))
+            .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn last_hash(&self, item: &str) -> Option<&str> {
+        self.items.get(item).map(|state| state.hash.as_str())
+    }
+
+    pub fn set(&mut self, item: &str, hash: &str) {
+        self.items.insert(
+            item.to_string(),
+            ItemState {
+                hash: hash.to_string(),
+            },
+        );
+    }
+}
+
+/// One content of a managed item and the worktrees that have it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Version {
+    pub hash: String,
+    pub worktrees: Vec<PathBuf>,
+    /// Differs from what the last sync left behind
+    pub changed: bool,
+}
+
+/// How the copies of a managed item relate to the last sync.
+#[derive(Debug)]
+pub enum Status {
+    /// No worktree has the item
+    Missing,
+    /// Every worktree that has the item still has the synced content
+    Unchanged(String),
+    /// Exactly one new content since the last sync, to be propagated everywhere
+    Changed(String),
+    /// Different changes in several worktrees (or, before the first sync, differing copies)
+    Conflict,
+}
+
+/// Groups the worktrees by the content `item` has in them, skipping worktrees without it.
+///
+/// Copies are hashed by their canonical path, so worktrees linking to the same copy (the
+/// primary worktree's or the shared store's) only hash it once.
+pub fn versions(
+    worktrees: &[PathBuf],
+    item: &str,
+    last_hash: Option<&str>,
+) -> Result<Vec<Version>, Error> {
+    let mut versions: Vec<Version> = Vec::new();
+    let mut hashes: BTreeMap<PathBuf, Option<String>> = BTreeMap::new();
+    for worktree in worktrees {
+        let Ok(path) = fs::canonicalize(worktree.join(item)) else {
+            continue;
+        };
+        let hash = match hashes.get(&path) {
+            Some(hash) => hash.clone(),
+            None => {
+                let hash = hash(&path)?;
+                hashes.insert(path, hash.clone());
+                hash
+            }
+        };
+        let Some(hash) = hash else {
+            continue;
+        };
+        match versions.iter_mut().find(|v| v.hash == hash) {
+            Some(version) => version.worktrees.push(worktree.clone()),
+            None => versions.push(Version {
+                changed: last_hash != Some(hash.as_str()),
+                hash,
+                worktrees: vec![worktree.clone()],
+            }),
+        }
+    }
+    Ok(versions)
+}
+
+/// Decides what to sync from the versions of an item.
+pub fn classify(versions: &[Version]) -> Status {
+    let changed = versions.iter().filter(|v| v.changed).collect::<Vec<_>>();
+    match changed.as_slice() {
+        _ if versions.is_empty() => Status::Missing,
+        [] => Status::Unchanged(versions[0].hash.clone()),
+        [version] => Status::Changed(version.hash.clone()),
+        _ => Status::Conflict,
+    }
+}
+
+/// What syncing an item does in one worktree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncAction {
+    /// Create a symlink to the source where the item is missing
+    Link,
+    /// Create a copy of the source where the item is missing
+    Copy,
+    /// Replace the item with a symlink or copy of the source, depending on the item's mode
+    Replace,
+    /// Leave the item alone
+    Skip,
+    /// Leave the item alone, since it was changed differently in several worktrees
+    Conflict,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Step {
+    pub worktree: PathBuf,
+    pub action: SyncAction,
+    /// What is at the item's path in the worktree before syncing
+    pub destination: Destination,
+    pub reason: String,
+    /// What is linked to or copied
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<PathBuf>,
+    /// Whether the worktree gets a symlink rather than a copy
+    #[serde(skip)]
+    pub link: bool,
+    /// A differing file or directory is replaced and is kept as a backup
+    pub backup: bool,
+}
+
+/// What is at an item's path in a worktree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Destination {
+    Missing,
+    File,
+    Directory,
+    /// A symlink that resolves to the source
+    Symlink,
+    /// A symlink that resolves to something other than the source
+    WrongSymlink,
+    /// A symlink whose target doesn't exist
+    BrokenSymlink,
+}
+
+impl Destination {
+    fn of(path: &Path, source: Option<&Path>) -> Destination {
+        let Ok(metadata) = path.symlink_metadata() else {
+            return Destination::Missing;
+        };
+        if !metadata.is_symlink() {
+            return if metadata.is_dir() {
+                Destination::Directory
+            } else {
+                Destination::File
+            };
+        }
+        match fs::canonicalize(path) {
+            Err(_) => Destination::BrokenSymlink,
+            Ok(target) if Some(target.as_path()) == source => Destination::Symlink,
+            Ok(_) => Destination::WrongSymlink,
+        }
+    }
+}
+
+/// What `gwt sync` does with a managed item, worktree by worktree.
+#[derive(Debug, Serialize)]
+pub struct ItemPlan {
+    pub path: String,
+    pub mode: Mode,
+    /// Lives in the shared store, which every worktree links to
+    pub shared: bool,
+    /// Why the item is synced the way it is
+    pub reason: String,
+    /// The copy of the synced version that copies are made from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<PathBuf>,
+    /// Content hash of the source, recorded in the sync state once the item is synced
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    pub steps: Vec<Step>,
+    /// The differing versions of an unresolved conflict
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub versions: Vec<Version>,
+}
+
+/// Works out what syncing every item does in every worktree. Both `gwt sync --dry-run` and the
+/// real sync use it, so the preview is what happens. Linked items point into `primary`.
+/// Conflicts are passed to `resolve`, which returns the hash of the version to keep or `None`
+/// to leave the item alone.
+pub fn plan(
+    worktrees: &[PathBuf],
+    primary: &Path,
+    items: &[ManagedItem],
+    default_mode: Mode,
+    state: &SyncState,
+    mut resolve: impl FnMut(&str, &[Version]) -> Result<Option<String>, Error>,
+) -> Result<Vec<ItemPlan>, Error> {
+    items
+        .iter()
+        .map(|item| {
+            let mode = item.mode.unwrap_or(default_mode);
+            plan_item(worktrees, primary, &item.path, mode, state, &mut resolve)
+        })
+        .collect()
+}
+
+fn plan_item(
+    worktrees: &[PathBuf],
+    primary: &Path,
+    item: &str,
+    mode: Mode,
+    state: &SyncState,
+    resolve: &mut impl FnMut(&str, &[Version]) -> Result<Option<String>, Error>,
+) -> Result<ItemPlan, Error> {
+    let mut plan = ItemPlan {
+        path: item.to_string(),
+        mode,
+        shared: false,
+        reason: String::new(),
+        source: None,
+        hash: None,
+        steps: Vec::new(),
+        versions: Vec::new(),
+    };
+    let skip_everywhere = |plan: &mut ItemPlan, reason: &str| {
+        plan.reason = reason.to_string();
+        plan.steps = worktrees
+            .iter()
+            .map(|worktree| Step {
+                worktree: worktree.clone(),
+                action: SyncAction::Skip,
+                destination: Destination::of(&worktree.join(&plan.path), None),
+                reason: reason.to_string(),
+                from: None,
+                link: false,
+                backup: false,
+            })
+            .collect();
+    };
+
+    if mode == Mode::Template {
+        skip_everywhere(&mut plan, "templates are not synced");
+        return Ok(plan);
+    }
+
+    let versions = versions(worktrees, item, state.last_hash(item))?;
+    let changed_in = || {
+        list(
+            versions
+                .iter()
+                .filter(|v| v.changed)
+                .flat_map(|v| &v.worktrees),
+        )
+    };
+    let hash = match classify(&versions) {
+        Status::Missing => {
+            skip_everywhere(&mut plan, "not found in any worktree");
+            return Ok(plan);
+        }
+        Status::Unchanged(hash) => {
+            plan.reason = "unchanged since the last sync".to_string();
+            hash
+        }
+        Status::Changed(hash) => {
+            plan.reason = format!("changed in {}", changed_in());
+            hash
+        }
+        Status::Conflict => match resolve(item, &versions)? {
+            Some(hash) => {
+                let kept = versions.iter().find(|v| v.hash == hash);
+                plan.reason = format!(
+                    "changed differently in {}, keeping the version from {}",
+                    changed_in(),
+                    list(kept.into_iter().flat_map(|v| &v.worktrees))
+                );
+                hash
+            }
+            None => {
+                plan.reason = format!("changed differently in {}", changed_in());
+                for worktree in worktrees {
+                    let reason = match versions.iter().find(|v| v.worktrees.contains(worktree)) {
+                        Some(version) if version.changed => "changed since the last sync",
+                        Some(_) => "has the last synced version",
+                        None => "missing",
+                    };
+                    plan.steps.push(Step {
+                        worktree: worktree.clone(),
+                        action: SyncAction::Conflict,
+                        destination: Destination::of(&worktree.join(item), None),
+                        reason: reason.to_string(),
+                        from: None,
+                        link: false,
+                        backup: false,
+                    });
+                }
+                plan.versions = versions;
+                return Ok(plan);
+            }
+        },
+    };
+
+    let version = versions
+        .iter()
+        .find(|v| v.hash == hash)
+        .expect("the synced hash is one of the versions");
+    // Copy from the primary worktree if it has a real copy of the version, otherwise from any
+    // real copy rather than another worktree's symlink.
+    let is_real = |worktree: &&PathBuf| !worktree.join(item).is_symlink();
+    let source_worktree = match version.worktrees.iter().find(|w| *w == primary) {
+        Some(primary) if is_real(&primary) => primary,
+        _ => version
+            .worktrees
+            .iter()
+            .find(is_real)
+            .unwrap_or(&version.worktrees[0]),
+    };
+    let source = fs::canonicalize(source_worktree.join(item))
+        .map_err(|e| format!("Failed to resolve {item}: {e}"))?;
+    // Linked items point into the primary worktree, which needs a real copy before anything
+    // links to it, so it comes first.
+    let link_target = primary.join(item);
+    let ordered = worktrees
+        .iter()
+        .filter(|w| *w == primary)
+        .chain(worktrees.iter().filter(|w| *w != primary));
+
+    for worktree in ordered {
+        let dest = worktree.join(item);
+        let gets_link = mode == Mode::Link && worktree != primary;
+        let from = if gets_link { &link_target } else { &source };
+        let destination = Destination::of(&dest, Some(from));
+        let current = versions.iter().find(|v| v.worktrees.contains(worktree));
+        let outdated = current.filter(|v| v.hash != hash).map(|v| {
+            if v.changed {
+                "was changed too"
+            } else {
+                "has the last synced version"
+            }
+        });
+        let mut backup = false;
+        let (action, reason) = match destination {
+            _ if !gets_link && (worktree == source_worktree || dest == source) => {
+                (SyncAction::Skip, "has the source".to_string())
+            }
+            Destination::Missing if gets_link => (SyncAction::Link, "missing".to_string()),
+            Destination::Missing => (SyncAction::Copy, "missing".to_string()),
+            Destination::BrokenSymlink => (SyncAction::Replace, "is a broken symlink".to_string()),
+            Destination::Symlink if gets_link => (SyncAction::Skip, "up to date".to_string()),
+            Destination::Symlink | Destination::WrongSymlink
+                if mode == Mode::Link && !gets_link =>
+            {
+                (
+                    SyncAction::Replace,
+                    "is a symlink, but the primary worktree needs a copy".to_string(),
+                )
+            }
+            Destination::Symlink | Destination::WrongSymlink if !gets_link => {
+                (SyncAction::Replace, "is a symlink, not a copy".to_string())
+            }
+            Destination::Symlink | Destination::WrongSymlink => {
+                let target = fs::read_link(&dest).unwrap_or_default();
+                (
+                    SyncAction::Replace,
+                    format!(
+                        "links to {} instead of the primary worktree",
+                        target.display()
+                    ),
+                )
+            }
+            Destination::File | Destination::Directory => match outdated {
+                Some(reason) => {
+                    backup = true;
+                    (SyncAction::Replace, format!("{reason}, backed up"))
+                }
+                None if gets_link => (SyncAction::Replace, "is a copy, not a symlink".to_string()),
+                None => (SyncAction::Skip, "up to date".to_string()),
+            },
+        };
+        let from = match action {
+            SyncAction::Link | SyncAction::Copy | SyncAction::Replace => Some(from.clone()),
+            SyncAction::Skip | SyncAction::Conflict => None,
+        };
+        plan.steps.push(Step {
+            worktree: worktree.clone(),
+            action,
+            destination,
+            reason,
+            from,
+            link: gets_link,
+            backup,
+        });
+    }
+    plan.source = Some(source);
+    plan.hash = Some(hash);
+    Ok(plan)
+}
+
+/// Works out how every worktree is linked to the items of the shared store. The store has the
+/// only real copy; copies found in worktrees are replaced, and backed up if they differ.
+pub fn plan_shared(worktrees: &[PathBuf], store: &Store) -> Result<Vec<ItemPlan>, Error> {
+    let mut plans = Vec::new();
+    for item in &store.items {
+        let mut plan = ItemPlan {
+            path: item.clone(),
+            mode: Mode::Link,
+            shared: true,
+            reason: String::new(),
+            source: None,
+            hash: None,
+            steps: Vec::new(),
+            versions: Vec::new(),
+        };
+        let source = fs::canonicalize(store.path(item)).ok();
+        let hash = match &source {
+            Some(source) => hash(source)?,
+            None => None,
+        };
+        let (Some(source), Some(hash)) = (source, hash) else {
+            plan.reason = "missing from the shared store".to_string();
+            plan.steps = worktrees
+                .iter()
+                .map(|worktree| Step {
+                    worktree: worktree.clone(),
+                    action: SyncAction::Skip,
+                    destination: Destination::of(&worktree.join(item), None),
+                    reason: "nothing to link to".to_string(),
+                    from: None,
+                    link: false,
+                    backup: false,
+                })
+                .collect();
+            plans.push(plan);
+            continue;
+        };
+
+        plan.reason = "lives in the shared store".to_string();
+        let link_target = store.path(item);
+        for worktree in worktrees {
+            let dest = worktree.join(item);
+            // Only a direct link counts: a link to another worktree's link into the store
+            // dangles once that worktree is removed.
+            let destination = match Destination::of(&dest, Some(&source)) {
+                Destination::Symlink
+                    if !fs::read_link(&dest)
+                        .is_ok_and(|target| target == link_target || target == source) =>
+                {
+                    Destination::WrongSymlink
+                }
+                destination => destination,
+            };
+            let mut backup = false;
+            let (action, reason) = match destination {
+                Destination::Missing => (SyncAction::Link, "missing".to_string()),
+                Destination::Symlink => (SyncAction::Skip, "up to date".to_string()),
+                Destination::BrokenSymlink => {
+                    (SyncAction::Replace, "is a broken symlink".to_string())
+                }
+                Destination::WrongSymlink => {
+                    let target = fs::read_link(&dest).unwrap_or_default();
+                    (
+                        SyncAction::Replace,
+                        format!("links to {} instead of the shared store", target.display()),
+                    )
+                }
+                Destination::File | Destination::Directory => {
+                    if self::hash(&dest)?.as_deref() == Some(hash.as_str()) {
+                        (SyncAction::Replace, "is a copy, not a symlink".to_string())
+                    } else {
+                        backup = true;
+                        (
+                            SyncAction::Replace,
+                            "differs from the shared copy, backed up".to_string(),
+                        )
+                    }
+                }
+            };
+            let from = (action != SyncAction::Skip).then(|| link_target.clone());
+            plan.steps.push(Step {
+                worktree: worktree.clone(),
+                action,
+                destination,
+                reason,
+                from,
+                link: true,
+                backup,
+            });
+        }
+        plan.source = Some(source);
+        plan.hash = Some(hash);
+        plans.push(plan);
+    }
+    Ok(plans)
+}
+
+fn list<'a>(worktrees: impl Iterator<Item = &'a PathBuf>) -> String {
+    worktrees
+        .map(|w| w.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Content hash of a file or directory, following symlinks; `None` if there is nothing at
+/// `path`. Files are hashed like git blobs, directories by a manifest of their files' hashes.
+pub fn hash(path: &Path) -> Result<Option<String>, Error> {
+    let Ok(metadata) = fs::metadata(path) else {
+        return Ok(None);
+    };
+    if !metadata.is_dir() {
+        return Ok(hash_objects(&[path.to_path_buf()])?.pop());
+    }
+
+    let files = WalkDir::new(path)
+        .min_depth(1)
+        .follow_links(true)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| !e.file_type().is_dir())
+        .map(|e| e.path().to_path_buf())
+        .collect::<Vec<_>>();
+    let mut manifest = String::from("directory\n");
+    for (file, hash) in files.iter().zip(hash_objects(&files)?) {
+        let relative = file.strip_prefix(path).unwrap_or(file);
+        manifest.push_str(&format!("{hash} {}\n", relative.display()));
+    }
+    git_hash_object(&["--stdin"], manifest.into_bytes()).map(|hashes| hashes.into_iter().next())
+}
+
+/// Hashes many files with a single `git hash-object` process.
+fn hash_objects(files: &[PathBuf]) -> Result<Vec<String>, Error> {
+    if files.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut paths = Vec::new();
+    for file in files {
+        paths.extend_from_slice(file.as_os_str().as_encoded_bytes());
+        paths.push(b'\n');
+    }
+    git_hash_object(&["--stdin-paths"], paths)
+}
+
+fn git_hash_object(args: &[&str], input: Vec<u8>) -> Result<Vec<String>, Error> {
+    let mut child = Command::new("git")
+        .arg("hash-object")
+        .arg("--no-filters")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to execute git hash-object: {e}"))?;
+
+    // Written from another thread, so git can't block on a full stdout pipe meanwhile.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let writer = thread::spawn(move || stdin.write_all(&input));
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Failed to execute git hash-object: {e}"))?;
+    let written = writer.join().expect("writing to git hash-object panicked");
+
+    if !output.status.success() || written.is_err() {
+        return Err(
+            Error::new(ErrorCode::GitFailed, "git hash-object failed").with_stderr(&output.stderr)
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    fn version(hash: &str, worktrees: &[&str], changed: bool) -> Version {
+        Version {
+            hash: hash.to_string(),
+            worktrees: worktrees.iter().map(PathBuf::from).collect(),
+            changed,
+        }
+    }
+
+    #[test]
+    fn classify_without_versions_is_missing() {
+        assert!(matches!(classify(&[]), Status::Missing));
+    }
+
+    #[test]
+    fn classify_without_changes_is_unchanged() {
+        let versions = [version("a", &["/w1", "/w2"], false)];
+        assert!(matches!(classify(&versions), Status::Unchanged(h) if h == "a"));
+    }
+
+    #[test]
+    fn classify_a_single_change_propagates_it() {
+        let versions = [
+            version("a", &["/w1", "/w3"], false),
+            version("b", &["/w2"], true),
+        ];
+        assert!(matches!(classify(&versions), Status::Changed(h) if h == "b"));
+    }
+
+    #[test]
+    fn classify_different_changes_conflict() {
+        let versions = [
+            version("a", &["/w1"], false),
+            version("b", &["/w2"], true),
+            version("c", &["/w3"], true),
+        ];
+        assert!(matches!(classify(&versions), Status::Conflict));
+    }
+
+    #[test]
+    fn versions_before_the_first_sync() {
+        let root = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(root.path()).unwrap();
+        let worktrees = ["w1", "w2", "w3"].map(|w| root.join(w));
+        for (worktree, content) in worktrees.iter().zip(["a", "a", "b"]) {
+            fs::create_dir(worktree).unwrap();
+            fs::write(worktree.join(".env"), content).unwrap();
+        }
+
+        // Identical copies are a single change to propagate, differing copies a conflict.
+        let same = versions(&worktrees[..2], ".env", None).unwrap();
+        assert_eq!(same.len(), 1);
+        assert_eq!(same[0].worktrees.len(), 2);
+        assert!(matches!(classify(&same), Status::Changed(_)));
+
+        let differing = versions(&worktrees, ".env", None).unwrap();
+        assert_eq!(differing.len(), 2);
+        assert!(differing.iter().all(|v| v.changed));
+        assert!(matches!(classify(&differing), Status::Conflict));
+    }
+
+    #[test]
+    fn versions_count_symlinked_copies_with_their_target() {
+        let root = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(root.path()).unwrap();
+        let worktrees = ["w1", "w2", "w3"].map(|w| root.join(w));
+        for worktree in &worktrees {
+            fs::create_dir(worktree).unwrap();
+        }
+        fs::create_dir(worktrees[0].join("cache")).unwrap();
+        fs::write(worktrees[0].join("cache/data"), "a").unwrap();
+        symlink(worktrees[0].join("cache"), worktrees[1].join("cache")).unwrap();
+        // A dangling link is a missing copy, not an error.
+        symlink(root.join("gone"), worktrees[2].join("cache")).unwrap();
+
+        let found = versions(&worktrees, "cache", None).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].worktrees, worktrees[..2]);
+    }
+
+    fn no_resolution(_: &str, _: &[Version]) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+
+    fn step<'a>(plan: &'a ItemPlan, worktree: &Path) -> &'a Step {
+        plan.steps.iter().find(|s| s.worktree == worktree).unwrap()
+    }
+
+    #[test]
+    fn plan_item_acts_on_what_is_in_each_worktree() {
+        let root = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(root.path()).unwrap();
+        let names = [
+            "primary", "missing", "same", "old", "link", "wrong", "broken",
+        ];
+        let worktrees = names.map(|w| root.join(w));
+        for worktree in &worktrees {
+            fs::create_dir(worktree).unwrap();
+        }
+        let [primary, missing, same, old, link, wrong, broken] = &worktrees;
+        fs::write(root.join("elsewhere"), "old").unwrap();
+        fs::write(primary.join(".env"), "new").unwrap();
+        fs::write(same.join(".env"), "new").unwrap();
+        fs::write(old.join(".env"), "old").unwrap();
+        symlink(primary.join(".env"), link.join(".env")).unwrap();
+        symlink(root.join("elsewhere"), wrong.join(".env")).unwrap();
+        symlink(root.join("nothing"), broken.join(".env")).unwrap();
+
+        // .env was "old" after the last sync and changed to "new" in the primary worktree.
+        let mut state = SyncState::default();
+        state.set(".env", &hash(&old.join(".env")).unwrap().unwrap());
+
+        let plan = plan_item(
+            &worktrees,
+            primary,
+            ".env",
+            Mode::Link,
+            &state,
+            &mut no_resolution,
+        )
+        .unwrap();
+        assert_eq!(plan.source.as_deref(), Some(primary.join(".env").as_path()));
+        assert_eq!(plan.steps[0].worktree, *primary);
+
+        let expected = [
+            (primary, Destination::File, SyncAction::Skip, false),
+            (missing, Destination::Missing, SyncAction::Link, false),
+            (same, Destination::File, SyncAction::Replace, false),
+            (old, Destination::File, SyncAction::Replace, true),
+            (link, Destination::Symlink, SyncAction::Skip, false),
+            (wrong, Destination::WrongSymlink, SyncAction::Replace, false),
+            (
+                broken,
+                Destination::BrokenSymlink,
+                SyncAction::Replace,
+                false,
+            ),
+        ];
+        for (worktree, destination, action, backup) in expected {
+            let step = step(&plan, worktree);
+            assert_eq!(step.destination, destination, "{}", worktree.display());
+            assert_eq!(step.action, action, "{}", worktree.display());
+            assert_eq!(step.backup, backup, "{}", worktree.display());
+            let linked = action != SyncAction::Skip;
+            assert_eq!(step.from.is_some(), linked, "{}", worktree.display());
+            if linked {
+                assert!(step.link);
+                assert_eq!(step.from.as_deref(), Some(primary.join(".env").as_path()));
+            }
+        }
+    }
+
+    #[test]
+    fn plan_item_replaces_copied_directories_with_links() {
+        let root = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(root.path()).unwrap();
+        let worktrees = ["primary", "copy"].map(|w| root.join(w));
+        for worktree in &worktrees {
+            fs::create_dir_all(worktree.join("cache")).unwrap();
+            fs::write(worktree.join("cache/data"), "1").unwrap();
+        }
+
+        let plan = plan_item(
+            &worktrees,
+            &worktrees[0],
+            "cache",
+            Mode::Link,
+            &SyncState::default(),
+            &mut no_resolution,
+        )
+        .unwrap();
+        let step = step(&plan, &worktrees[1]);
+        assert_eq!(step.destination, Destination::Directory);
+        assert_eq!(step.action, SyncAction::Replace);
+        assert!(!step.backup);
+    }
+
+    #[test]
+    fn plan_item_copies_where_missing_and_leaves_copies_alone() {
+        let root = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(root.path()).unwrap();
+        let worktrees = ["primary", "copy", "missing"].map(|w| root.join(w));
+        for worktree in &worktrees {
+            fs::create_dir(worktree).unwrap();
+        }
+        fs::write(worktrees[0].join(".env"), "a").unwrap();
+        fs::write(worktrees[1].join(".env"), "a").unwrap();
+
+        let plan = plan_item(
+            &worktrees,
+            &worktrees[0],
+            ".env",
+            Mode::Copy,
+            &SyncState::default(),
+            &mut no_resolution,
+        )
+        .unwrap();
+        assert_eq!(step(&plan, &worktrees[1]).action, SyncAction::Skip);
+        let missing = step(&plan, &worktrees[2]);
+        assert_eq!(missing.action, SyncAction::Copy);
+        assert!(!missing.link);
+    }
+
+    #[test]
+    fn plan_item_leaves_unresolved_conflicts_alone() {
+        let root = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(root.path()).unwrap();
+        let worktrees = ["a", "b"].map(|w| root.join(w));
+        for (worktree, content) in worktrees.iter().zip(["1", "2"]) {
+            fs::create_dir(worktree).unwrap();
+            fs::write(worktree.join(".env"), content).unwrap();
+        }
+
+        let plan = plan_item(
+            &worktrees,
+            &worktrees[0],
+            ".env",
+            Mode::Link,
+            &SyncState::default(),
+            &mut no_resolution,
+        )
+        .unwrap();
+        assert_eq!(plan.versions.len(), 2);
+        assert!(plan
+            .steps
+            .iter()
+            .all(|s| s.action == SyncAction::Conflict && s.from.is_none()));
+    }
+}